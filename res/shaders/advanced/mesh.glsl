@@ -4,14 +4,12 @@ struct VertexData {
 	vec4 position;
 };
 
-uniform mat4 mvp;
+#include "../include/blocks.glsl"
+
 uniform mat4 modelMatrix;
 
 #pragma vertex
-layout (location = 0) in vec3 aPos;
-layout (location = 1) in vec3 aNormal;
-layout (location = 2) in vec3 aColor;
-layout (location = 3) in vec2 aUV;
+#include "../include/vertex_layout.glsl"
 
 out VertexData vData;
 
@@ -28,13 +26,13 @@ in VertexData vData[];
 
 @entry void geometry()
 {
-	gl_Position = mvp * vData[0].position;
+	gl_Position = viewProjection * modelMatrix * vData[0].position;
 	EmitVertex();
-	gl_Position = mvp * vData[1].position;
+	gl_Position = viewProjection * modelMatrix * vData[1].position;
 	EmitVertex();
-	gl_Position = mvp * vData[2].position;
+	gl_Position = viewProjection * modelMatrix * vData[2].position;
 	EmitVertex();
-	gl_Position = mvp * vData[0].position;
+	gl_Position = viewProjection * modelMatrix * vData[0].position;
 	EmitVertex();
 	EndPrimitive();
 }
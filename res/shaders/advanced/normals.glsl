@@ -4,14 +4,12 @@ struct VertexData {
 	vec3 normal;
 };
 
-uniform mat4 mvp;
+#include "../include/blocks.glsl"
+
 uniform mat4 modelMatrix;
 
 #pragma vertex
-layout (location = 0) in vec3 aPos;
-layout (location = 1) in vec3 aNormal;
-layout (location = 2) in vec3 aColor;
-layout (location = 3) in vec2 aUV;
+#include "../include/vertex_layout.glsl"
 
 out VertexData vData;
 
@@ -37,11 +35,11 @@ out VertexData fData;
 		vec3 n = vData[i].normal;
 
 		fData.normal = vData[i].normal;
-		gl_Position = mvp * vec4(p, 1.0);
+		gl_Position = viewProjection * modelMatrix * vec4(p, 1.0);
 		EmitVertex();
 
 		fData.normal = vData[i].normal;
-		gl_Position = mvp * vec4(p + n * normal_length, 1.0);
+		gl_Position = viewProjection * modelMatrix * vec4(p + n * normal_length, 1.0);
 		EmitVertex();
 		EndPrimitive();
 	}
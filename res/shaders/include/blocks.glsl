@@ -0,0 +1,21 @@
+// Shared with every shader, see renderer/uniform_buffer.rs for the Rust side
+
+layout (std140) uniform CameraBlock {
+	mat4 view;
+	mat4 projection;
+	mat4 viewProjection;
+	vec3 cameraPosition;
+};
+
+#define MAX_LIGHTS 4
+
+struct DirectionalLight {
+	vec3 direction;
+	vec3 color;
+};
+
+layout (std140) uniform LightsBlock {
+	DirectionalLight lights[MAX_LIGHTS];
+	vec3 ambient;
+	int lightCount;
+};
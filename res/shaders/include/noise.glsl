@@ -0,0 +1,9 @@
+float rand(float n) {
+	return fract(sin(n) * 43758.5453123);
+}
+
+float noise(float p) {
+	float fl = floor(p);
+	float fc = fract(p);
+	return mix(rand(fl), rand(fl + 1.0), fc);
+}
@@ -0,0 +1,8 @@
+layout (location = 0) in vec3 aPos;
+layout (location = 1) in vec3 aNormal;
+layout (location = 2) in vec3 aColor;
+layout (location = 3) in vec2 aUV;
+layout (location = 4) in vec4 aTangent;
+layout (location = 5) in vec2 aUV2;
+layout (location = 6) in uvec4 aBoneIndices;
+layout (location = 7) in vec4 aBoneWeights;
@@ -1,22 +1,29 @@
 #version 410 core
 
+#pragma keywords USE_TEXTURE USE_LIGHTING USE_NORMAL_MAP
+
 struct VertexOut {
 	vec4 position;
+	vec3 worldPosition;
 	vec3 normal;
 	vec2 uv;
 };
 
-uniform mat4 mvp;
+#include "include/blocks.glsl"
+
 uniform mat4 modelMatrix;
+
+#ifdef USE_TEXTURE
 uniform sampler2D diffuseTex;
 uniform float mixFactor;
-uniform float lightFactor;
+#endif
+
+#ifdef USE_NORMAL_MAP
+uniform sampler2D normalTex;
+#endif
 
 #pragma vertex
-layout (location = 0) in vec3 aPos;
-layout (location = 1) in vec3 aNormal;
-layout (location = 2) in vec3 aColor;
-layout (location = 3) in vec2 aUV;
+#include "include/vertex_layout.glsl"
 
 out VertexOut vData;
 
@@ -26,8 +33,9 @@ out VertexOut vData;
 
 	vData.normal = normalMatrix * aNormal;
 	vData.position = vec4(aPos.x, aPos.y, aPos.z, 1.0);
+	vData.worldPosition = (modelMatrix * vData.position).xyz;
 	vData.uv = aUV;
-	gl_Position = mvp * vData.position;
+	gl_Position = viewProjection * modelMatrix * vData.position;
 }
 
 #pragma fragment
@@ -35,40 +43,67 @@ in VertexOut vData;
 
 out vec4 FragColor;
 
-vec3 phong(vec3 lightColor, vec3 lightDir, vec3 ambient, vec3 objectColor) {
-	float diff = max(dot(vData.normal, lightDir), 0.0);
-	vec3 diffuse = diff * lightColor * objectColor;
-	return(ambient + diffuse);
+#ifdef USE_NORMAL_MAP
+// The vertex layout has no tangents, the frame comes from screen-space
+// derivatives of the position and UVs instead
+mat3 cotangentFrame(vec3 normal, vec3 position, vec2 uv) {
+	vec3 dp1 = dFdx(position);
+	vec3 dp2 = dFdy(position);
+	vec2 duv1 = dFdx(uv);
+	vec2 duv2 = dFdy(uv);
+
+	vec3 dp2perp = cross(dp2, normal);
+	vec3 dp1perp = cross(normal, dp1);
+	vec3 tangent = dp2perp * duv1.x + dp1perp * duv2.x;
+	vec3 bitangent = dp2perp * duv1.y + dp1perp * duv2.y;
+
+	float invmax = inversesqrt(max(dot(tangent, tangent), dot(bitangent, bitangent)));
+	return mat3(tangent * invmax, bitangent * invmax, normal);
 }
+#endif
 
-vec3 normalColor(vec3 normal) {
-	return (vec3(1.0) + normal) * 0.5;
+vec3 surfaceNormal() {
+	vec3 normal = normalize(vData.normal);
+
+#ifdef USE_NORMAL_MAP
+	vec3 mapped = texture(normalTex, vData.uv).xyz * 2.0 - 1.0;
+	normal = normalize(cotangentFrame(normal, vData.worldPosition, vData.uv) * mapped);
+#endif
+
+	return normal;
 }
 
-float rand(float n) {
-	return fract(sin(n) * 43758.5453123);
+vec3 phong(vec3 objectColor) {
+	vec3 normal = surfaceNormal();
+	vec3 diffuse = vec3(0.0);
+
+	for (int i = 0; i < lightCount; i++) {
+		float diff = max(dot(normal, lights[i].direction), 0.0);
+		diffuse += diff * lights[i].color;
+	}
+
+	return (ambient + diffuse) * objectColor;
 }
 
-float noise(float p) {
-	float fl = floor(p);
-	float fc = fract(p);
-	return mix(rand(fl), rand(fl + 1.0), fc);
+vec3 normalColor(vec3 normal) {
+	return (vec3(1.0) + normal) * 0.5;
 }
 
+#include "include/noise.glsl"
+
 @entry void fragment()
 {
-	vec3 lightDir = vec3(1.0, 0.0, 0.0);
-	vec3 lightColor = vec3(1.0, 1.0, 1.0);
-	float ambientStength = 0.2;
-	vec3 ambient = ambientStength * lightColor;
-
 	float n = noise(gl_PrimitiveID);
-	vec4 faceColor = vec4(n, n, n, 1.0);
+	vec4 color = vec4(n, n, n, 1.0);
 
+#ifdef USE_TEXTURE
 	vec4 texColor = texture(diffuseTex, vData.uv);
-	vec4 textureFaceMix = mix(faceColor, texColor, mixFactor);
-	vec4 lightPure = vec4(phong(lightColor, lightDir, ambient, vec3(1.0)), 1.0);
-	vec4 light = mix(vec4(1.0), lightPure, lightFactor);
+	color = mix(color, texColor, mixFactor);
+#endif
+
+#ifdef USE_LIGHTING
+	color.rgb = phong(color.rgb);
+#endif
 
-    FragColor = textureFaceMix * light;
+	FragColor = color;
 }
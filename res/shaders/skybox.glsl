@@ -0,0 +1,32 @@
+#version 410 core
+
+#include "include/blocks.glsl"
+
+uniform samplerCube environment;
+
+#pragma vertex
+layout (location = 0) in vec3 aPos;
+
+out vec3 direction;
+
+@entry void vertex()
+{
+	direction = aPos;
+
+	// Only the rotation of the camera, the skybox is infinitely far away
+	mat4 rotation = mat4(mat3(view));
+
+	// w for z puts every fragment on the far plane
+	vec4 position = projection * rotation * vec4(aPos, 1.0);
+	gl_Position = position.xyww;
+}
+
+#pragma fragment
+in vec3 direction;
+
+out vec4 FragColor;
+
+@entry void fragment()
+{
+	FragColor = texture(environment, direction);
+}
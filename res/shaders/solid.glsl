@@ -1,17 +1,19 @@
 #version 410 core
 
+#include "include/blocks.glsl"
+
+uniform mat4 modelMatrix;
+
 #pragma vertex
 layout (location = 0) in vec3 aPos;
 layout (location = 2) in vec3 aColor;
 
 layout (location = 0) out vec3 color;
 
-uniform mat4 mvp;
-
 @entry void vertex()
 {
 	color = aColor;
-	gl_Position = mvp * vec4(aPos.x, aPos.y, aPos.z, 1.0);
+	gl_Position = viewProjection * modelMatrix * vec4(aPos.x, aPos.y, aPos.z, 1.0);
 }
 
 #pragma fragment
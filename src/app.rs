@@ -1,4 +1,5 @@
 use std::{
+    cell::RefCell,
     io::Write,
     rc::Rc,
     time::{Duration, Instant},
@@ -7,13 +8,19 @@ use std::{
 use sdl2::{event::Event, keyboard::Keycode, video::GLContext, video::Window, Sdl, VideoSubsystem};
 
 use crate::{
+    image::{Image, PixelFormat},
     renderer::{
         camera::Camera,
+        cubemap::Cubemap,
+        gl_state,
+        material::Material,
         math::{boundingbox::BoundingBox, matrix::Mat4, transform::Transform, vec::Vec3},
-        mesh::{Mesh, MeshData},
+        mesh::{BufferUsage, Mesh, MeshData},
         scene_object::SceneObject,
-        shader::Shader,
-        texture::Texture,
+        shader::{Shader, ShaderError},
+        skybox::Skybox,
+        texture::{self, Filter, FloatPrecision, Texture, Wrap},
+        uniform_buffer::{DirectionalLight, FrameUniforms, LightsBlock},
         Primitive, Renderer,
     },
     truevision::Targa,
@@ -37,6 +44,7 @@ struct Flags {
     display_debug_wireframe: bool,
     display_mesh: bool,
     display_texture: bool,
+    display_skybox: bool,
     light: bool,
 }
 
@@ -48,7 +56,6 @@ struct Objects {
 
 struct Meshes {
     target: Option<Rc<Mesh>>,
-    bounding_box: Option<Rc<Mesh>>,
 }
 
 struct AdvancedShaders {
@@ -57,9 +64,9 @@ struct AdvancedShaders {
 }
 
 struct Shaders {
-    target: Shader,
+    target: Rc<RefCell<Shader>>,
     advanced: AdvancedShaders,
-    bounding_box: Shader,
+    bounding_box: Rc<RefCell<Shader>>,
 }
 
 struct Keys {
@@ -93,8 +100,12 @@ struct State {
     translation_speed: f32,
     relative_mouse_movement: Option<(i32, i32)>,
     keys: Keys,
-    diffuse_texture: Texture,
+    diffuse_texture: Rc<RefCell<Texture>>,
     mix_factor: f32,
+    skybox: Skybox,
+    last_shader_check: Instant,
+    frame_uniforms: FrameUniforms,
+    lights: LightsBlock,
 }
 
 impl App {
@@ -107,12 +118,15 @@ impl App {
         };
 
         unsafe {
-            gl::ClearColor(0.2, 0.2, 0.2, 1.0);
-            gl::Enable(gl::DEPTH_TEST);
-            gl::Enable(gl::CULL_FACE);
+            // Clear colors are linear, this is 0.2 once encoded to sRGB
+            gl::ClearColor(0.033, 0.033, 0.033, 1.0);
+            gl_state::set_capability(gl::DEPTH_TEST, true);
+            gl_state::set_capability(gl::CULL_FACE, true);
             gl::CullFace(gl::BACK);
             gl::Hint(gl::LINE_SMOOTH_HINT, gl::NICEST);
-            gl::Enable(gl::LINE_SMOOTH);
+            gl_state::set_capability(gl::LINE_SMOOTH, true);
+            gl_state::set_capability(gl::TEXTURE_CUBE_MAP_SEAMLESS, true);
+            gl_state::set_capability(gl::FRAMEBUFFER_SRGB, true);
         }
 
         ret
@@ -208,6 +222,80 @@ impl App {
             "C" => {
                 self.set_camera_control(state, !state.flags.user_camera_control);
             }
+            "O" => {
+                state.flags.display_skybox = !state.flags.display_skybox;
+                println!("flags.display_skybox: {}", state.flags.display_skybox);
+            }
+            "G" => {
+                self.set_camera_control(state, false);
+                print!("Path to texture: ");
+                std::io::stdout().flush().unwrap();
+
+                let mut buffer = String::new();
+                std::io::stdin().read_line(&mut buffer).unwrap();
+
+                Self::load_texture(buffer.trim(), state);
+            }
+            "E" => {
+                self.set_camera_control(state, false);
+                print!("Path to equirectangular environment image: ");
+                std::io::stdout().flush().unwrap();
+
+                let mut buffer = String::new();
+                std::io::stdin().read_line(&mut buffer).unwrap();
+
+                Self::load_environment(buffer.trim(), state);
+            }
+            "F" => {
+                let mut options = state.diffuse_texture.borrow().options();
+
+                // Nearest -> bilinear -> trilinear
+                (
+                    options.min_filter,
+                    options.mag_filter,
+                    options.mipmap_filter,
+                ) = match (options.min_filter, options.mipmap_filter) {
+                    (Filter::Nearest, _) => (Filter::Linear, Filter::Linear, None),
+                    (Filter::Linear, None) => {
+                        (Filter::Linear, Filter::Linear, Some(Filter::Linear))
+                    }
+                    (Filter::Linear, Some(_)) => (Filter::Nearest, Filter::Nearest, None),
+                };
+
+                state.diffuse_texture.borrow_mut().set_options(options);
+                println!(
+                    "texture filter: min {:?}, mag {:?}, mipmap {:?}",
+                    options.min_filter, options.mag_filter, options.mipmap_filter
+                );
+            }
+            "R" => {
+                let mut options = state.diffuse_texture.borrow().options();
+
+                let wrap = match options.wrap_s {
+                    Wrap::Repeat => Wrap::MirroredRepeat,
+                    Wrap::MirroredRepeat => Wrap::ClampToEdge,
+                    Wrap::ClampToEdge => Wrap::Repeat,
+                };
+
+                options.wrap_s = wrap;
+                options.wrap_t = wrap;
+
+                state.diffuse_texture.borrow_mut().set_options(options);
+                println!("texture wrap: {:?}", wrap);
+            }
+            "Y" => {
+                let mut options = state.diffuse_texture.borrow().options();
+
+                match texture::max_anisotropy() {
+                    Some(max) => {
+                        options.anisotropy = if options.anisotropy > 1.0 { 1.0 } else { max };
+
+                        state.diffuse_texture.borrow_mut().set_options(options);
+                        println!("texture anisotropy: {}", options.anisotropy);
+                    }
+                    None => println!("Anisotropic filtering is not supported by the driver"),
+                }
+            }
             "W" => state.keys.forward = true,
             "A" => state.keys.left = true,
             "S" => state.keys.back = true,
@@ -261,6 +349,13 @@ impl App {
                     }
                 }
             }
+            Event::DropFile { filename, .. } => {
+                if filename.to_ascii_lowercase().ends_with(".obj") {
+                    self.load_model(&filename, state);
+                } else {
+                    Self::load_texture(&filename, state);
+                }
+            }
             Event::MouseMotion { xrel, yrel, .. } => {
                 if state.flags.user_camera_control {
                     state.relative_mouse_movement = Some((xrel, yrel));
@@ -371,38 +466,24 @@ impl App {
     unsafe fn render(&mut self, state: &mut State) {
         gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
 
+        state.frame_uniforms.update(&state.camera, &state.lights);
+
         if let Some(ref mut scene_object) = state.objects.target {
             if state.flags.display_mesh {
-                state.diffuse_texture.bind_slot(0);
-
-                let mix_factor_location = state.shaders.target.uniform_location("mixFactor");
-                let light_factor_location = state.shaders.target.uniform_location("lightFactor");
-
-                let light_factor = state.flags.light as i32 as f32;
-
-                state
-                    .shaders
-                    .target
-                    .set_uniform_1f_opt(light_factor_location, light_factor);
+                let material = &mut scene_object.material;
 
-                state
-                    .shaders
-                    .target
-                    .set_uniform_1f_opt(mix_factor_location, state.mix_factor);
+                // Still blending the texture in or out
+                material.set_keyword("USE_TEXTURE", state.mix_factor > 0.0);
+                material.set_keyword("USE_LIGHTING", state.flags.light);
+                material.set_uniform("mixFactor", state.mix_factor);
 
-                Renderer::draw_object(
-                    &scene_object,
-                    &state.shaders.target,
-                    &state.camera,
-                    Primitive::Triangles,
-                );
+                Renderer::draw(scene_object, Primitive::Triangles);
             }
 
             if state.flags.display_debug_normals {
                 Renderer::draw_object(
                     &scene_object,
                     &state.shaders.advanced.normals,
-                    &state.camera,
                     Primitive::Triangles,
                 );
             }
@@ -411,7 +492,6 @@ impl App {
                 Renderer::draw_object(
                     &scene_object,
                     &state.shaders.advanced.mesh,
-                    &state.camera,
                     Primitive::Triangles,
                 );
             }
@@ -419,24 +499,17 @@ impl App {
             if state.flags.display_bounding_box {
                 if let Some(ref mut bbox) = state.objects.bounding_box {
                     bbox.transform = scene_object.transform.clone();
-                    Renderer::draw_object(
-                        bbox,
-                        &state.shaders.bounding_box,
-                        &state.camera,
-                        Primitive::Wireframe,
-                    );
+                    Renderer::draw(bbox, Primitive::Wireframe);
                 }
             }
         }
 
         if state.flags.display_axes {
-            let axes = &state.objects.axes;
-            Renderer::draw_object(
-                axes,
-                &state.shaders.bounding_box,
-                &state.camera,
-                Primitive::Wireframe,
-            );
+            Renderer::draw(&state.objects.axes, Primitive::Wireframe);
+        }
+
+        if state.flags.display_skybox {
+            Renderer::draw_skybox(&state.skybox);
         }
     }
 
@@ -485,8 +558,49 @@ impl App {
         }
     }
 
-    fn create_bounding_box_mesh(bounding_box: Option<BoundingBox>) -> Option<Mesh> {
-        let bounding_box = bounding_box?;
+    fn handle_shader_error(path: &str, error: ShaderError) {
+        let detail = match error {
+            ShaderError::VertexCompileError(log)
+            | ShaderError::FragmentCompileError(log)
+            | ShaderError::GeometryCompileError(log)
+            | ShaderError::TessControlCompileError(log)
+            | ShaderError::TessEvaluationCompileError(log)
+            | ShaderError::LinkError(log) => log,
+            other => format!("{:?}", other),
+        };
+
+        eprintln!("{path}\n\x1b[0;31merror:\x1b[0m {detail}");
+        eprintln!("Keeping the previous version of the shader");
+    }
+
+    /// Rebuilds the shaders whose files changed on disk
+    fn reload_shaders(state: &mut State) {
+        let mut target = state.shaders.target.borrow_mut();
+        let mut bounding_box = state.shaders.bounding_box.borrow_mut();
+
+        let shaders = [
+            &mut *target,
+            &mut state.shaders.advanced.normals,
+            &mut state.shaders.advanced.mesh,
+            &mut *bounding_box,
+            &mut state.skybox.shader,
+        ];
+
+        for shader in shaders {
+            let path = shader
+                .path()
+                .map(|path| path.display().to_string())
+                .unwrap_or_default();
+
+            match shader.reload() {
+                Ok(true) => println!("Reloaded '{path}'"),
+                Ok(false) => {}
+                Err(error) => Self::handle_shader_error(&path, error),
+            }
+        }
+    }
+
+    fn bounding_box_mesh_data(bounding_box: BoundingBox) -> MeshData {
         let vertices = bounding_box.get_vertices();
 
         let mut mesh_data = MeshData::new();
@@ -513,7 +627,68 @@ impl App {
             .cloned()
             .collect();
 
-        Some(Mesh::new(&mesh_data))
+        mesh_data
+    }
+
+    /// Vertical sky-to-ground gradient, used until an environment is loaded
+    fn create_default_environment() -> Image {
+        let height = 64;
+        let mut components = vec![];
+
+        for y in 0..height {
+            let t = y as f32 / (height - 1) as f32;
+
+            let color = if t < 0.5 {
+                let t = t * 2.0;
+                [0.25 + 0.45 * t, 0.45 + 0.35 * t, 0.8 + 0.1 * t]
+            } else {
+                let t = (t - 0.5) * 2.0;
+                [0.7 - 0.5 * t, 0.8 - 0.62 * t, 0.9 - 0.75 * t]
+            };
+
+            components.extend_from_slice(&color);
+        }
+
+        Image::from_components(1, height, PixelFormat::Rgb8, &components)
+    }
+
+    /// Replaces the diffuse texture, keeping its sampling options. The previous
+    /// texture is released when dropped
+    fn load_texture(path: &str, state: &mut State) {
+        let image = match Image::from_file(path) {
+            Ok(image) => image,
+            Err(error) => {
+                eprintln!("{:?}", error);
+                return;
+            }
+        };
+
+        println!(
+            "Successfully loaded '{path}'. Size: {}x{}",
+            image.width, image.height
+        );
+
+        let options = state.diffuse_texture.borrow().options();
+        *state.diffuse_texture.borrow_mut() = Texture::from_image_with_options(&image, options);
+        state.flags.display_texture = true;
+    }
+
+    fn load_environment(path: &str, state: &mut State) {
+        let image = match Image::from_file(path) {
+            Ok(image) => image,
+            Err(error) => {
+                eprintln!("{:?}", error);
+                return;
+            }
+        };
+
+        let size = (image.width / 4).max(1);
+        let environment = Cubemap::from_equirectangular(&image, size, FloatPrecision::Half);
+
+        println!("Successfully loaded '{path}'. Cubemap faces: {size}x{size}");
+
+        state.skybox.environment = environment;
+        state.flags.display_skybox = true;
     }
 
     fn load_model(&mut self, path: &str, state: &mut State) {
@@ -546,21 +721,45 @@ impl App {
         state.meshes.target = Some(Rc::new(Mesh::new(&mesh_data)));
 
         if let Some(ref mesh) = state.meshes.target {
-            state.objects.target = Some(SceneObject::new(mesh.clone(), transform.clone()));
-        }
+            let mut material = Material::new(state.shaders.target.clone());
+            material.set_texture("diffuseTex", state.diffuse_texture.clone());
 
-        state.meshes.bounding_box =
-            Self::create_bounding_box_mesh(state.bounding_box).map(|m| Rc::new(m));
+            state.objects.target =
+                Some(SceneObject::new(mesh.clone(), material, transform.clone()));
+        }
 
-        let bounding_box_object = match state.meshes.bounding_box {
-            Some(ref mesh) => Some(SceneObject::new(mesh.clone(), Transform::default())),
-            None => None,
+        let bounding_box_data = match state.bounding_box {
+            Some(bounding_box) => Self::bounding_box_mesh_data(bounding_box),
+            None => {
+                state.objects.bounding_box = None;
+                return;
+            }
         };
 
-        state.objects.bounding_box = bounding_box_object;
+        // The box keeps its 8 corners, so its buffer is updated in place
+        let updated = state
+            .objects
+            .bounding_box
+            .as_mut()
+            .and_then(SceneObject::mesh_mut)
+            .is_some_and(|mesh| mesh.update_vertices(0, &bounding_box_data).is_ok());
+
+        if !updated {
+            let mesh = Mesh::with_usage(
+                &bounding_box_data,
+                bounding_box_data.layout(),
+                BufferUsage::Dynamic,
+            );
+
+            state.objects.bounding_box = Some(SceneObject::new(
+                Rc::new(mesh),
+                Material::new(state.shaders.bounding_box.clone()),
+                Transform::default(),
+            ));
+        }
     }
 
-    pub fn run(&mut self, model_path: Option<&str>) {
+    pub fn run(&mut self, model_path: Option<&str>, texture_path: Option<&str>) {
         let mut event_pump = self.sdl.event_pump().unwrap();
 
         let shader = Shader::from_file("res/shaders/phong.glsl").unwrap();
@@ -578,12 +777,20 @@ impl App {
         let start_time = Instant::now();
 
         let bounding_box_shader = Shader::from_file("res/shaders/solid.glsl").unwrap();
+        let bounding_box_shader = Rc::new(RefCell::new(bounding_box_shader));
 
         let axes_mesh = Rc::new(Mesh::new(&MeshData::axes()));
 
         let targa = Targa::from_file("res/textures/mlp.tga").unwrap();
 
-        let diffuse_texture = Texture::from_targa(&targa);
+        let diffuse_texture = Rc::new(RefCell::new(Texture::from_image(&targa.image)));
+
+        let skybox_shader = Shader::from_file("res/shaders/skybox.glsl").unwrap();
+        let environment = Cubemap::from_equirectangular(
+            &Self::create_default_environment(),
+            64,
+            FloatPrecision::Half,
+        );
 
         let mut state = State {
             camera,
@@ -604,19 +811,21 @@ impl App {
                 display_mesh: true,
                 display_debug_wireframe: false,
                 display_texture: false,
+                display_skybox: false,
                 light: false,
             },
-            meshes: Meshes {
-                target: None,
-                bounding_box: None,
-            },
+            meshes: Meshes { target: None },
             objects: Objects {
                 target: None,
                 bounding_box: None,
-                axes: SceneObject::new(axes_mesh, Transform::default()),
+                axes: SceneObject::new(
+                    axes_mesh,
+                    Material::new(bounding_box_shader.clone()),
+                    Transform::default(),
+                ),
             },
             shaders: Shaders {
-                target: shader,
+                target: Rc::new(RefCell::new(shader)),
                 advanced: AdvancedShaders {
                     normals: normal_shader_3d,
                     mesh: mesh_shader,
@@ -641,12 +850,26 @@ impl App {
             translation_speed: 5.0,
             diffuse_texture,
             mix_factor: 0.0,
+            skybox: Skybox::new(environment, skybox_shader),
+            last_shader_check: start_time,
+            frame_uniforms: FrameUniforms::new(),
+            lights: LightsBlock {
+                lights: vec![DirectionalLight {
+                    direction: Vec3(1.0, 0.0, 0.0),
+                    color: Vec3(1.0, 1.0, 1.0),
+                }],
+                ambient: Vec3(0.2, 0.2, 0.2),
+            },
         };
 
         if let Some(path) = model_path {
             self.load_model(path, &mut state);
         }
 
+        if let Some(path) = texture_path {
+            Self::load_texture(path, &mut state);
+        }
+
         let mut last_frame_time = start_time;
 
         while state.running {
@@ -658,6 +881,12 @@ impl App {
             let delta_time = current_time.duration_since(last_frame_time);
             last_frame_time = current_time;
 
+            // Polling is cheap enough at this rate, no need for a file watcher
+            if current_time.duration_since(state.last_shader_check) > Duration::from_millis(500) {
+                Self::reload_shaders(&mut state);
+                state.last_shader_check = current_time;
+            }
+
             unsafe {
                 self.update(&mut state, delta_time);
                 self.render(&mut state);
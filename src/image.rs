@@ -0,0 +1,423 @@
+use std::path::PathBuf;
+
+use crate::{
+    png::{self, PngError},
+    radiance::{Radiance, RadianceError},
+    truevision::{Targa, TargaError},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    R8,
+    Rg8,
+    Rgb8,
+    Bgr8,
+    Rgba8,
+    Bgra8,
+    /// Native-endian `f32` components, for high-dynamic-range images
+    Rgb32F,
+    Rgba32F,
+}
+
+impl PixelFormat {
+    pub fn channels(&self) -> usize {
+        match self {
+            Self::R8 => 1,
+            Self::Rg8 => 2,
+            Self::Rgb8 | Self::Bgr8 | Self::Rgb32F => 3,
+            Self::Rgba8 | Self::Bgra8 | Self::Rgba32F => 4,
+        }
+    }
+
+    pub fn bytes_per_component(&self) -> usize {
+        if self.is_float() {
+            4
+        } else {
+            1
+        }
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(self, Self::Rgb32F | Self::Rgba32F)
+    }
+
+    pub fn bytes_per_pixel(&self) -> usize {
+        self.channels() * self.bytes_per_component()
+    }
+
+    pub fn has_alpha(&self) -> bool {
+        matches!(self, Self::Rgba8 | Self::Bgra8 | Self::Rgba32F)
+    }
+
+    /// Same channels with red and blue swapped, if the format has both
+    pub fn swapped_red_blue(&self) -> Self {
+        match self {
+            Self::Rgb8 => Self::Bgr8,
+            Self::Bgr8 => Self::Rgb8,
+            Self::Rgba8 => Self::Bgra8,
+            Self::Bgra8 => Self::Rgba8,
+            other => *other,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ImageError {
+    UnknownFormat(PathBuf),
+    TargaError(TargaError),
+    PngError(PngError),
+    RadianceError(RadianceError),
+}
+
+impl From<TargaError> for ImageError {
+    fn from(value: TargaError) -> Self {
+        Self::TargaError(value)
+    }
+}
+
+impl From<PngError> for ImageError {
+    fn from(value: PngError) -> Self {
+        Self::PngError(value)
+    }
+}
+
+impl From<RadianceError> for ImageError {
+    fn from(value: RadianceError) -> Self {
+        Self::RadianceError(value)
+    }
+}
+
+/// Decoded pixels, top row first. Rows may be padded, in which case `stride`
+/// is larger than `width * format.bytes_per_pixel()`
+#[derive(Debug, Clone)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub format: PixelFormat,
+    pub stride: usize,
+    pub bytes: Vec<u8>,
+}
+
+impl Image {
+    /// Decodes any supported file, picking the decoder from the extension
+    pub fn from_file<P: Into<PathBuf>>(path: P) -> Result<Self, ImageError> {
+        let path: PathBuf = path.into();
+
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase());
+
+        match extension.as_deref() {
+            Some("tga") => Ok(Targa::from_file(path)?.image),
+            Some("png") => Ok(png::from_file(path)?),
+            Some("hdr") => Ok(Radiance::from_file(path)?.image),
+            _ => Err(ImageError::UnknownFormat(path)),
+        }
+    }
+
+    /// Creates an image from tightly packed rows
+    pub fn new(width: usize, height: usize, format: PixelFormat, bytes: Vec<u8>) -> Self {
+        Self::with_stride(
+            width,
+            height,
+            format,
+            width * format.bytes_per_pixel(),
+            bytes,
+        )
+    }
+
+    pub fn from_f32(width: usize, height: usize, format: PixelFormat, components: &[f32]) -> Self {
+        assert!(format.is_float());
+
+        let bytes = components.iter().flat_map(|c| c.to_ne_bytes()).collect();
+
+        Self::new(width, height, format, bytes)
+    }
+
+    pub fn with_stride(
+        width: usize,
+        height: usize,
+        format: PixelFormat,
+        stride: usize,
+        bytes: Vec<u8>,
+    ) -> Self {
+        assert!(stride >= width * format.bytes_per_pixel());
+        assert!(bytes.len() >= stride * height);
+
+        Self {
+            width,
+            height,
+            format,
+            stride,
+            bytes,
+        }
+    }
+
+    pub fn is_packed(&self) -> bool {
+        self.stride == self.row_size()
+    }
+
+    /// Size in bytes of the pixels of a row, without padding
+    pub fn row_size(&self) -> usize {
+        self.width * self.format.bytes_per_pixel()
+    }
+
+    pub fn row(&self, y: usize) -> &[u8] {
+        let start = y * self.stride;
+        &self.bytes[start..(start + self.row_size())]
+    }
+
+    pub fn row_mut(&mut self, y: usize) -> &mut [u8] {
+        let start = y * self.stride;
+        let end = start + self.row_size();
+        &mut self.bytes[start..end]
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> &[u8] {
+        let bytes_per_pixel = self.format.bytes_per_pixel();
+        &self.row(y)[(x * bytes_per_pixel)..((x + 1) * bytes_per_pixel)]
+    }
+
+    /// Copy of the image without row padding
+    pub fn to_packed(&self) -> Self {
+        let bytes = (0..self.height)
+            .flat_map(|y| self.row(y))
+            .copied()
+            .collect();
+
+        Self::new(self.width, self.height, self.format, bytes)
+    }
+
+    /// Turns BGR(A) into RGB(A) and the other way around
+    pub fn swap_red_blue(&mut self) {
+        let bytes_per_pixel = self.format.bytes_per_pixel();
+
+        if self.format.swapped_red_blue() == self.format {
+            return;
+        }
+
+        for y in 0..self.height {
+            for pixel in self.row_mut(y).chunks_exact_mut(bytes_per_pixel) {
+                pixel.swap(0, 2);
+            }
+        }
+
+        self.format = self.format.swapped_red_blue();
+    }
+
+    /// Converts BGR(A) images to RGB(A), leaving other formats unchanged
+    pub fn into_rgb(mut self) -> Self {
+        if matches!(self.format, PixelFormat::Bgr8 | PixelFormat::Bgra8) {
+            self.swap_red_blue();
+        }
+
+        self
+    }
+
+    /// Converts any format to 8-bit RGBA. Single-channel images become gray,
+    /// float components are clamped to [0, 1]
+    pub fn to_rgba8(&self) -> Self {
+        let channels = self.format.channels();
+        let mut components = Vec::with_capacity(self.width * self.height * 4);
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let mut pixel = [0.0, 0.0, 0.0, 1.0];
+
+                for (channel, component) in pixel.iter_mut().enumerate().take(channels) {
+                    *component = self.component(x, y, channel);
+                }
+
+                match self.format {
+                    PixelFormat::R8 => (pixel[1], pixel[2]) = (pixel[0], pixel[0]),
+                    PixelFormat::Bgr8 | PixelFormat::Bgra8 => pixel.swap(0, 2),
+                    _ => {}
+                }
+
+                components.extend_from_slice(&pixel);
+            }
+        }
+
+        Self::from_components(self.width, self.height, PixelFormat::Rgba8, &components)
+    }
+
+    pub fn flip_vertical(&mut self) {
+        let row_size = self.row_size();
+
+        for y in 0..(self.height / 2) {
+            let (top, bottom) = self.bytes.split_at_mut((self.height - 1 - y) * self.stride);
+            top[(y * self.stride)..(y * self.stride + row_size)]
+                .swap_with_slice(&mut bottom[..row_size]);
+        }
+    }
+
+    pub fn flip_horizontal(&mut self) {
+        let bytes_per_pixel = self.format.bytes_per_pixel();
+
+        for y in 0..self.height {
+            let row = self.row_mut(y);
+            let width = row.len() / bytes_per_pixel;
+
+            for x in 0..(width / 2) {
+                let (left, right) = row.split_at_mut((width - 1 - x) * bytes_per_pixel);
+                left[(x * bytes_per_pixel)..((x + 1) * bytes_per_pixel)]
+                    .swap_with_slice(&mut right[..bytes_per_pixel]);
+            }
+        }
+    }
+
+    /// Reads a component, normalized to [0, 1] for 8-bit formats
+    fn component(&self, x: usize, y: usize, channel: usize) -> f32 {
+        let pixel = self.pixel(x, y);
+
+        if self.format.is_float() {
+            let bytes = &pixel[(channel * 4)..(channel * 4 + 4)];
+            f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+        } else {
+            pixel[channel] as f32 / 255.0
+        }
+    }
+
+    fn write_component(format: PixelFormat, bytes: &mut [u8], index: usize, value: f32) {
+        if format.is_float() {
+            bytes[(index * 4)..(index * 4 + 4)].copy_from_slice(&value.to_ne_bytes());
+        } else {
+            bytes[index] = (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+        }
+    }
+
+    /// Creates a tightly packed image from components, normalized to [0, 1]
+    /// for 8-bit formats
+    pub fn from_components(
+        width: usize,
+        height: usize,
+        format: PixelFormat,
+        components: &[f32],
+    ) -> Self {
+        let mut bytes = vec![0; width * height * format.bytes_per_pixel()];
+
+        for (index, &value) in components.iter().enumerate() {
+            Self::write_component(format, &mut bytes, index, value);
+        }
+
+        Self::new(width, height, format, bytes)
+    }
+
+    /// Bilinearly filtered components at normalized coordinates, wrapping
+    /// horizontally and clamping vertically. Unused channels are zero
+    pub fn sample(&self, u: f32, v: f32) -> [f32; 4] {
+        let x = u.rem_euclid(1.0) * self.width as f32 - 0.5;
+        let y = (v.clamp(0.0, 1.0) * self.height as f32 - 0.5).max(0.0);
+
+        let x0 = x.floor();
+        let fx = x - x0;
+        let x0 = (x0 as isize).rem_euclid(self.width as isize) as usize;
+        let x1 = (x0 + 1) % self.width;
+
+        let y0 = (y as usize).min(self.height - 1);
+        let y1 = (y0 + 1).min(self.height - 1);
+        let fy = y - y0 as f32;
+
+        let mut components = [0.0; 4];
+
+        for (channel, component) in components
+            .iter_mut()
+            .enumerate()
+            .take(self.format.channels())
+        {
+            let top =
+                self.component(x0, y0, channel) * (1.0 - fx) + self.component(x1, y0, channel) * fx;
+            let bottom =
+                self.component(x0, y1, channel) * (1.0 - fx) + self.component(x1, y1, channel) * fx;
+
+            *component = top * (1.0 - fy) + bottom * fy;
+        }
+
+        components
+    }
+
+    /// Bilinear resampling to a new size. The result is tightly packed
+    pub fn resize(&self, width: usize, height: usize) -> Self {
+        let channels = self.format.channels();
+        let mut bytes = vec![0; width * height * self.format.bytes_per_pixel()];
+
+        if self.width == 0 || self.height == 0 {
+            return Self::new(width, height, self.format, bytes);
+        }
+
+        let scale_x = self.width as f32 / width as f32;
+        let scale_y = self.height as f32 / height as f32;
+
+        for y in 0..height {
+            // Sample at pixel centers
+            let source_y = ((y as f32 + 0.5) * scale_y - 0.5).max(0.0);
+            let y0 = (source_y as usize).min(self.height - 1);
+            let y1 = (y0 + 1).min(self.height - 1);
+            let fy = source_y - y0 as f32;
+
+            for x in 0..width {
+                let source_x = ((x as f32 + 0.5) * scale_x - 0.5).max(0.0);
+                let x0 = (source_x as usize).min(self.width - 1);
+                let x1 = (x0 + 1).min(self.width - 1);
+                let fx = source_x - x0 as f32;
+
+                for channel in 0..channels {
+                    let top = self.component(x0, y0, channel) * (1.0 - fx)
+                        + self.component(x1, y0, channel) * fx;
+                    let bottom = self.component(x0, y1, channel) * (1.0 - fx)
+                        + self.component(x1, y1, channel) * fx;
+
+                    let index = (y * width + x) * channels + channel;
+                    let value = top * (1.0 - fy) + bottom * fy;
+                    Self::write_component(self.format, &mut bytes, index, value);
+                }
+            }
+        }
+
+        Self::new(width, height, self.format, bytes)
+    }
+
+    /// Raises every color component to the power of `gamma`. Alpha is left
+    /// untouched
+    pub fn apply_gamma(&mut self, gamma: f32) {
+        let channels = self.format.channels();
+        let color_channels = if self.format.has_alpha() {
+            channels - 1
+        } else {
+            channels
+        };
+
+        if self.format.is_float() {
+            for y in 0..self.height {
+                for pixel in self.row_mut(y).chunks_exact_mut(channels * 4) {
+                    for component in pixel[..(color_channels * 4)].chunks_exact_mut(4) {
+                        let value = f32::from_ne_bytes([
+                            component[0],
+                            component[1],
+                            component[2],
+                            component[3],
+                        ]);
+                        component.copy_from_slice(&value.max(0.0).powf(gamma).to_ne_bytes());
+                    }
+                }
+            }
+
+            return;
+        }
+
+        let mut table = [0u8; 256];
+        for (value, entry) in table.iter_mut().enumerate() {
+            let corrected = (value as f32 / 255.0).powf(gamma);
+            *entry = (corrected * 255.0).round() as u8;
+        }
+
+        for y in 0..self.height {
+            for pixel in self.row_mut(y).chunks_exact_mut(channels) {
+                for component in &mut pixel[..color_channels] {
+                    *component = table[*component as usize];
+                }
+            }
+        }
+    }
+}
@@ -3,31 +3,28 @@
 mod app;
 mod commands;
 
+pub mod image;
 pub mod opengl;
+pub mod png;
+pub mod radiance;
 pub mod renderer;
 pub mod truevision;
 pub mod utils;
 pub mod wavefront;
 
-use std::ffi::CStr;
-
 use app::App;
+use opengl::driver::DriverInfo;
 use sdl2::video::GLProfile;
 use truevision::Targa;
 
 fn display_driver_info() {
-    let to_str = |raw: *const u8| {
-        let cstr = unsafe { CStr::from_ptr(raw as *const i8) };
-        cstr.to_str().expect("Invalid UTF-8")
-    };
-
-    unsafe {
-        let vendor = to_str(gl::GetString(gl::VENDOR));
-        let renderer = to_str(gl::GetString(gl::RENDERER));
-        let version = to_str(gl::GetString(gl::VERSION));
+    let DriverInfo {
+        vendor,
+        renderer,
+        version,
+    } = DriverInfo::query();
 
-        println!("OpenGL info:\n  Vendor: {vendor}\n  Renderer:  {renderer}\n  Version: {version}");
-    }
+    println!("OpenGL info:\n  Vendor: {vendor}\n  Renderer:  {renderer}\n  Version: {version}");
 }
 
 fn main() {
@@ -36,13 +33,19 @@ fn main() {
     let sdl = sdl2::init().unwrap();
     let video = sdl.video().unwrap();
 
-    let args: Vec<_> = args.collect();
-
-    let model_path = match args.len() {
-        1 => None,
-        2 => Some(args[1].as_str()),
-        _ => panic!("Invalid number of arguments"),
-    };
+    let mut args = args.skip(1);
+    let mut model_path = None;
+    let mut texture_path = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--texture" => {
+                texture_path = Some(args.next().expect("Missing path after --texture"));
+            }
+            _ if model_path.is_none() => model_path = Some(arg),
+            _ => panic!("Invalid number of arguments"),
+        }
+    }
 
     let attributes = video.gl_attr();
 
@@ -52,6 +55,7 @@ fn main() {
     attributes.set_depth_size(24);
     attributes.set_stencil_size(8);
     attributes.set_double_buffer(true);
+    attributes.set_framebuffer_srgb_compatible(true);
 
     let window = video
         .window("Scop (no model)", size.0, size.1)
@@ -68,5 +72,5 @@ fn main() {
 
     let mut app = App::new(sdl, video, context, window);
 
-    app.run(model_path);
+    app.run(model_path.as_deref(), texture_path.as_deref());
 }
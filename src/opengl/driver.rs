@@ -0,0 +1,32 @@
+use std::ffi::CStr;
+
+/// The strings identifying the OpenGL implementation, queried from the
+/// current context
+#[derive(Debug, Clone)]
+pub struct DriverInfo {
+    pub vendor: String,
+    pub renderer: String,
+    pub version: String,
+}
+
+impl DriverInfo {
+    pub fn query() -> Self {
+        let get = |name| unsafe {
+            let raw = gl::GetString(name);
+
+            if raw.is_null() {
+                return String::new();
+            }
+
+            CStr::from_ptr(raw as *const i8)
+                .to_string_lossy()
+                .into_owned()
+        };
+
+        Self {
+            vendor: get(gl::VENDOR),
+            renderer: get(gl::RENDERER),
+            version: get(gl::VERSION),
+        }
+    }
+}
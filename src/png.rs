@@ -0,0 +1,753 @@
+use std::{fs::File, io::Read, path::PathBuf};
+
+use crate::image::{Image, PixelFormat};
+
+use self::inflate::InflateError;
+
+pub mod inflate;
+
+const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Larger images are rejected before anything is allocated for them
+const MAX_DIMENSION: usize = 16384;
+
+#[derive(Debug)]
+pub enum PngError {
+    IoError(std::io::Error),
+    InvalidSignature,
+    TruncatedChunk,
+    ChunkCrcMismatch([u8; 4]),
+    UnknownCriticalChunk([u8; 4]),
+    MissingHeader,
+    InvalidHeader,
+    UnsupportedFormat { color_type: u8, bit_depth: u8 },
+    ZeroDimensions,
+    DimensionsTooLarge,
+    SizeOverflow,
+    MissingPalette,
+    InvalidPalette,
+    InvalidTransparency,
+    MissingImageData,
+    TruncatedImageData,
+    InvalidFilter(u8),
+    InflateError(InflateError),
+}
+
+impl From<std::io::Error> for PngError {
+    fn from(value: std::io::Error) -> Self {
+        Self::IoError(value)
+    }
+}
+
+impl From<InflateError> for PngError {
+    fn from(value: InflateError) -> Self {
+        Self::InflateError(value)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ColorType {
+    Grayscale,
+    TrueColor,
+    Indexed,
+    GrayscaleAlpha,
+    TrueColorAlpha,
+}
+
+impl ColorType {
+    fn channels(&self) -> usize {
+        match self {
+            Self::Grayscale | Self::Indexed => 1,
+            Self::GrayscaleAlpha => 2,
+            Self::TrueColor => 3,
+            Self::TrueColorAlpha => 4,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct PngHeader {
+    width: usize,
+    height: usize,
+    bit_depth: u8,
+    color_type: ColorType,
+    interlaced: bool,
+}
+
+impl PngHeader {
+    fn from_bytes(bytes: &[u8]) -> Result<Self, PngError> {
+        if bytes.len() != 13 {
+            return Err(PngError::InvalidHeader);
+        }
+
+        let width = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize;
+        let height = u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]) as usize;
+        let bit_depth = bytes[8];
+
+        let color_type = match (bytes[9], bit_depth) {
+            (0, 1 | 2 | 4 | 8 | 16) => ColorType::Grayscale,
+            (2, 8 | 16) => ColorType::TrueColor,
+            (3, 1 | 2 | 4 | 8) => ColorType::Indexed,
+            (4, 8 | 16) => ColorType::GrayscaleAlpha,
+            (6, 8 | 16) => ColorType::TrueColorAlpha,
+            (color_type, bit_depth) => {
+                return Err(PngError::UnsupportedFormat {
+                    color_type,
+                    bit_depth,
+                })
+            }
+        };
+
+        // Compression and filter methods only have one defined value
+        if bytes[10] != 0 || bytes[11] != 0 || bytes[12] > 1 {
+            return Err(PngError::InvalidHeader);
+        }
+
+        if width == 0 || height == 0 {
+            return Err(PngError::ZeroDimensions);
+        }
+
+        if width > MAX_DIMENSION || height > MAX_DIMENSION {
+            return Err(PngError::DimensionsTooLarge);
+        }
+
+        Ok(Self {
+            width,
+            height,
+            bit_depth,
+            color_type,
+            interlaced: bytes[12] == 1,
+        })
+    }
+
+    fn bits_per_pixel(&self) -> usize {
+        self.color_type.channels() * self.bit_depth as usize
+    }
+
+    /// Size in bytes of a filtered scanline, without its filter type byte
+    fn stride(&self, width: usize) -> Option<usize> {
+        Some(width.checked_mul(self.bits_per_pixel())?.div_ceil(8))
+    }
+
+    /// Size of the (sub)image whose pixels land on
+    /// (`x_start` + n * `x_step`, `y_start` + n * `y_step`)
+    fn pass_dimensions(
+        &self,
+        (x_start, y_start): (usize, usize),
+        (x_step, y_step): (usize, usize),
+    ) -> (usize, usize) {
+        (
+            (self.width + x_step - 1 - x_start) / x_step,
+            (self.height + y_step - 1 - y_start) / y_step,
+        )
+    }
+
+    /// Size in bytes of the filtered scanlines of a (sub)image, with their
+    /// filter type bytes
+    fn filtered_size(&self, width: usize, height: usize) -> Option<usize> {
+        if width == 0 || height == 0 {
+            return Some(0);
+        }
+
+        (self.stride(width)? + 1).checked_mul(height)
+    }
+
+    /// Size in bytes of the decompressed image data, every pass included
+    fn image_data_size(&self) -> Option<usize> {
+        if !self.interlaced {
+            return self.filtered_size(self.width, self.height);
+        }
+
+        ADAM7_PASSES
+            .iter()
+            .try_fold(0usize, |total, &(start, step)| {
+                let (width, height) = self.pass_dimensions(start, step);
+                total.checked_add(self.filtered_size(width, height)?)
+            })
+    }
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+
+    for &byte in bytes {
+        crc ^= byte as u32;
+
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+
+    !crc
+}
+
+struct Chunk<'a> {
+    typ: [u8; 4],
+    data: &'a [u8],
+}
+
+fn read_chunk<'a>(data: &'a [u8], offset: &mut usize) -> Result<Chunk<'a>, PngError> {
+    let length = data
+        .get(*offset..(*offset + 4))
+        .ok_or(PngError::TruncatedChunk)?;
+    let length = u32::from_be_bytes([length[0], length[1], length[2], length[3]]) as usize;
+
+    // Type and data, followed by the CRC of both
+    let end = (*offset + 8)
+        .checked_add(length)
+        .ok_or(PngError::TruncatedChunk)?;
+    let body = data
+        .get((*offset + 4)..end)
+        .ok_or(PngError::TruncatedChunk)?;
+    let crc = data.get(end..(end + 4)).ok_or(PngError::TruncatedChunk)?;
+    let crc = u32::from_be_bytes([crc[0], crc[1], crc[2], crc[3]]);
+
+    let typ = [body[0], body[1], body[2], body[3]];
+
+    if crc32(body) != crc {
+        return Err(PngError::ChunkCrcMismatch(typ));
+    }
+
+    *offset = end + 4;
+
+    Ok(Chunk {
+        typ,
+        data: &body[4..],
+    })
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// Reverses scanline filtering in place. `data` holds `height` scanlines of
+/// `stride` bytes, each prefixed with its filter type
+fn unfilter(
+    data: &mut [u8],
+    stride: usize,
+    height: usize,
+    bytes_per_pixel: usize,
+) -> Result<(), PngError> {
+    let mut previous = vec![0u8; stride];
+
+    for row in data.chunks_exact_mut(stride + 1).take(height) {
+        let (filter, line) = row.split_at_mut(1);
+
+        match filter[0] {
+            0 => {}
+            1 => {
+                for i in bytes_per_pixel..stride {
+                    line[i] = line[i].wrapping_add(line[i - bytes_per_pixel]);
+                }
+            }
+            2 => {
+                for i in 0..stride {
+                    line[i] = line[i].wrapping_add(previous[i]);
+                }
+            }
+            3 => {
+                for i in 0..stride {
+                    let left = if i >= bytes_per_pixel {
+                        line[i - bytes_per_pixel]
+                    } else {
+                        0
+                    };
+                    let average = ((left as u16 + previous[i] as u16) / 2) as u8;
+                    line[i] = line[i].wrapping_add(average);
+                }
+            }
+            4 => {
+                for i in 0..stride {
+                    let (left, upper_left) = if i >= bytes_per_pixel {
+                        (line[i - bytes_per_pixel], previous[i - bytes_per_pixel])
+                    } else {
+                        (0, 0)
+                    };
+                    line[i] = line[i].wrapping_add(paeth(left, previous[i], upper_left));
+                }
+            }
+            other => return Err(PngError::InvalidFilter(other)),
+        }
+
+        previous.copy_from_slice(line);
+    }
+
+    Ok(())
+}
+
+/// Reads the sample at `index` of a scanline, at the image bit depth
+fn sample(line: &[u8], index: usize, header: &PngHeader) -> u16 {
+    match header.bit_depth {
+        8 => line[index] as u16,
+        16 => u16::from_be_bytes([line[2 * index], line[2 * index + 1]]),
+        bit_depth => {
+            let bit_depth = bit_depth as usize;
+            let bit = index * bit_depth;
+            let shift = 8 - bit_depth - bit % 8;
+
+            ((line[bit / 8] >> shift) & ((1 << bit_depth) - 1)) as u16
+        }
+    }
+}
+
+/// Reduces a sample to 8 bits. Indexed samples are returned as-is so they
+/// can be looked up in the palette
+fn to_8_bits(value: u16, header: &PngHeader) -> u8 {
+    match header.bit_depth {
+        8 => value as u8,
+        // Keep the most significant byte
+        16 => (value >> 8) as u8,
+        _ if header.color_type == ColorType::Indexed => value as u8,
+        bit_depth => (value as usize * 255 / ((1 << bit_depth) - 1)) as u8,
+    }
+}
+
+/// What a `tRNS` chunk makes transparent
+#[derive(Debug, Clone, Copy)]
+enum Transparency<'a> {
+    /// Alpha of the first palette entries, the others are opaque
+    Palette(&'a [u8]),
+    /// Pixels of exactly this gray or RGB value, at the image bit depth
+    ColorKey([u16; 3]),
+}
+
+impl<'a> Transparency<'a> {
+    fn from_bytes(
+        bytes: &'a [u8],
+        header: &PngHeader,
+        palette: Option<&[u8]>,
+    ) -> Result<Option<Self>, PngError> {
+        let key = |index: usize| u16::from_be_bytes([bytes[2 * index], bytes[2 * index + 1]]);
+
+        match header.color_type {
+            ColorType::Indexed => {
+                let palette = palette.ok_or(PngError::MissingPalette)?;
+
+                if bytes.len() > palette.len() / 3 {
+                    return Err(PngError::InvalidTransparency);
+                }
+
+                Ok(Some(Self::Palette(bytes)))
+            }
+            ColorType::Grayscale if bytes.len() == 2 => Ok(Some(Self::ColorKey([key(0); 3]))),
+            ColorType::TrueColor if bytes.len() == 6 => {
+                Ok(Some(Self::ColorKey([key(0), key(1), key(2)])))
+            }
+            ColorType::Grayscale | ColorType::TrueColor => Err(PngError::InvalidTransparency),
+            // Images with an alpha channel have no use for it
+            ColorType::GrayscaleAlpha | ColorType::TrueColorAlpha => Ok(None),
+        }
+    }
+}
+
+struct Decoder<'a> {
+    header: PngHeader,
+    palette: Option<&'a [u8]>,
+    transparency: Option<Transparency<'a>>,
+    /// RGBA8 pixels, top to bottom
+    pixels: Vec<u8>,
+}
+
+impl<'a> Decoder<'a> {
+    /// Expands one unfiltered scanline of a (sub)image into RGBA
+    fn write_line(
+        &mut self,
+        line: &[u8],
+        y: usize,
+        (x_start, x_step): (usize, usize),
+        width: usize,
+    ) -> Result<(), PngError> {
+        let channels = self.header.color_type.channels();
+
+        for x in 0..width {
+            let mut raw = [0u16; 4];
+            for (channel, value) in raw.iter_mut().enumerate().take(channels) {
+                *value = sample(line, x * channels + channel, &self.header);
+            }
+
+            let samples = raw.map(|value| to_8_bits(value, &self.header));
+
+            let key_alpha = |key: [u16; 3]| match self.transparency {
+                Some(Transparency::ColorKey(color_key)) if color_key == key => 0,
+                _ => 255,
+            };
+
+            let rgba = match self.header.color_type {
+                ColorType::Grayscale => {
+                    let alpha = key_alpha([raw[0]; 3]);
+                    [samples[0], samples[0], samples[0], alpha]
+                }
+                ColorType::GrayscaleAlpha => [samples[0], samples[0], samples[0], samples[1]],
+                ColorType::TrueColor => {
+                    let alpha = key_alpha([raw[0], raw[1], raw[2]]);
+                    [samples[0], samples[1], samples[2], alpha]
+                }
+                ColorType::TrueColorAlpha => [samples[0], samples[1], samples[2], samples[3]],
+                ColorType::Indexed => {
+                    let palette = self.palette.ok_or(PngError::MissingPalette)?;
+                    let index = samples[0] as usize;
+                    let color = palette
+                        .get((index * 3)..(index * 3 + 3))
+                        .ok_or(PngError::InvalidPalette)?;
+
+                    let alpha = match self.transparency {
+                        Some(Transparency::Palette(alphas)) => {
+                            alphas.get(index).copied().unwrap_or(255)
+                        }
+                        _ => 255,
+                    };
+
+                    [color[0], color[1], color[2], alpha]
+                }
+            };
+
+            let offset = (y * self.header.width + x_start + x * x_step) * 4;
+            self.pixels[offset..(offset + 4)].copy_from_slice(&rgba);
+        }
+
+        Ok(())
+    }
+
+    /// Unfilters and expands a (sub)image whose pixels land on
+    /// (`x_start` + n * `x_step`, `y_start` + n * `y_step`)
+    fn decode_pass(
+        &mut self,
+        data: &mut [u8],
+        (x_start, y_start): (usize, usize),
+        (x_step, y_step): (usize, usize),
+    ) -> Result<usize, PngError> {
+        let (width, height) = self
+            .header
+            .pass_dimensions((x_start, y_start), (x_step, y_step));
+
+        if width == 0 || height == 0 {
+            return Ok(0);
+        }
+
+        let stride = self.header.stride(width).ok_or(PngError::SizeOverflow)?;
+        let size = self
+            .header
+            .filtered_size(width, height)
+            .ok_or(PngError::SizeOverflow)?;
+
+        let data = data.get_mut(..size).ok_or(PngError::TruncatedImageData)?;
+        let bytes_per_pixel = self.header.bits_per_pixel().div_ceil(8);
+
+        unfilter(data, stride, height, bytes_per_pixel)?;
+
+        for (row, line) in data.chunks_exact(stride + 1).enumerate() {
+            let y = y_start + row * y_step;
+            self.write_line(&line[1..], y, (x_start, x_step), width)?;
+        }
+
+        Ok(size)
+    }
+}
+
+const ADAM7_PASSES: [((usize, usize), (usize, usize)); 7] = [
+    ((0, 0), (8, 8)),
+    ((4, 0), (8, 8)),
+    ((0, 4), (4, 8)),
+    ((2, 0), (4, 4)),
+    ((0, 2), (2, 4)),
+    ((1, 0), (2, 2)),
+    ((0, 1), (1, 2)),
+];
+
+/// Decodes a PNG file into an RGB image, or RGBA if it has an alpha channel
+/// or a `tRNS` chunk
+pub fn from_file<P: Into<PathBuf>>(path: P) -> Result<Image, PngError> {
+    let mut file = File::open(path.into())?;
+
+    let mut data = Vec::with_capacity(4096);
+
+    file.read_to_end(&mut data)?;
+
+    from_bytes(&data)
+}
+
+pub fn from_bytes(data: &[u8]) -> Result<Image, PngError> {
+    if !data.starts_with(SIGNATURE) {
+        return Err(PngError::InvalidSignature);
+    }
+
+    let mut offset = SIGNATURE.len();
+
+    let chunk = read_chunk(data, &mut offset)?;
+    if &chunk.typ != b"IHDR" {
+        return Err(PngError::MissingHeader);
+    }
+
+    let header = PngHeader::from_bytes(chunk.data)?;
+
+    let mut palette = None;
+    let mut transparency = None;
+    let mut compressed = vec![];
+
+    loop {
+        let chunk = read_chunk(data, &mut offset)?;
+
+        match &chunk.typ {
+            b"PLTE" => {
+                if chunk.data.is_empty() || chunk.data.len() % 3 != 0 {
+                    return Err(PngError::InvalidPalette);
+                }
+
+                palette = Some(chunk.data);
+            }
+            // Comes after the palette, which it needs
+            b"tRNS" => transparency = Transparency::from_bytes(chunk.data, &header, palette)?,
+            b"IDAT" => compressed.extend_from_slice(chunk.data),
+            b"IEND" => break,
+            // Ancillary chunks have a lowercase first letter and may be skipped
+            typ if typ[0].is_ascii_lowercase() => {}
+            typ => return Err(PngError::UnknownCriticalChunk(*typ)),
+        }
+    }
+
+    if compressed.is_empty() {
+        return Err(PngError::MissingImageData);
+    }
+
+    let image_data_size = header.image_data_size().ok_or(PngError::SizeOverflow)?;
+
+    let mut filtered = inflate::zlib_decompress(&compressed, image_data_size)?;
+
+    // Pixels are only allocated once the file has proven to hold them
+    if filtered.len() < image_data_size {
+        return Err(PngError::TruncatedImageData);
+    }
+
+    let pixels_size = header
+        .width
+        .checked_mul(header.height)
+        .and_then(|count| count.checked_mul(4))
+        .ok_or(PngError::SizeOverflow)?;
+
+    let mut decoder = Decoder {
+        header,
+        palette,
+        transparency,
+        pixels: vec![0; pixels_size],
+    };
+
+    if decoder.header.interlaced {
+        let mut position = 0;
+
+        for (start, step) in ADAM7_PASSES {
+            let data = filtered
+                .get_mut(position..)
+                .ok_or(PngError::TruncatedImageData)?;
+            position += decoder.decode_pass(data, start, step)?;
+        }
+    } else {
+        decoder.decode_pass(&mut filtered, (0, 0), (1, 1))?;
+    }
+
+    let (width, height) = (decoder.header.width, decoder.header.height);
+
+    let has_alpha = matches!(
+        decoder.header.color_type,
+        ColorType::GrayscaleAlpha | ColorType::TrueColorAlpha
+    ) || decoder.transparency.is_some();
+
+    if has_alpha {
+        return Ok(Image::new(
+            width,
+            height,
+            PixelFormat::Rgba8,
+            decoder.pixels,
+        ));
+    }
+
+    let bytes = decoder
+        .pixels
+        .chunks_exact(4)
+        .flat_map(|rgba| [rgba[0], rgba[1], rgba[2]])
+        .collect();
+
+    Ok(Image::new(width, height, PixelFormat::Rgb8, bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(typ: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut body = typ.to_vec();
+        body.extend_from_slice(data);
+
+        let mut bytes = (data.len() as u32).to_be_bytes().to_vec();
+        bytes.extend_from_slice(&body);
+        bytes.extend_from_slice(&crc32(&body).to_be_bytes());
+        bytes
+    }
+
+    /// A zlib stream of one stored block
+    fn stored(data: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![0x78, 0x01, 0x01];
+        bytes.extend_from_slice(&(data.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(&(!(data.len() as u16)).to_le_bytes());
+        bytes.extend_from_slice(data);
+        bytes.extend_from_slice(&inflate::adler32(data).to_be_bytes());
+        bytes
+    }
+
+    fn png(width: u32, height: u32, color_type: u8, interlaced: bool, filtered: &[u8]) -> Vec<u8> {
+        png_with_chunks(width, height, color_type, interlaced, &[], filtered)
+    }
+
+    /// A PNG with `chunks` between the header and the image data
+    fn png_with_chunks(
+        width: u32,
+        height: u32,
+        color_type: u8,
+        interlaced: bool,
+        chunks: &[(&[u8; 4], &[u8])],
+        filtered: &[u8],
+    ) -> Vec<u8> {
+        let mut header = width.to_be_bytes().to_vec();
+        header.extend_from_slice(&height.to_be_bytes());
+        header.extend_from_slice(&[8, color_type, 0, 0, interlaced as u8]);
+
+        let mut bytes = SIGNATURE.to_vec();
+        bytes.extend(chunk(b"IHDR", &header));
+        for (typ, data) in chunks {
+            bytes.extend(chunk(typ, data));
+        }
+        bytes.extend(chunk(b"IDAT", &stored(filtered)));
+        bytes.extend(chunk(b"IEND", &[]));
+        bytes
+    }
+
+    #[test]
+    fn unfilters_every_filter_type() {
+        let mut data = [
+            1, 1, 2, // Sub
+            2, 1, 1, // Up
+            3, 1, 1, // Average
+            4, 0, 1, // Paeth
+        ];
+
+        unfilter(&mut data, 2, 4, 1).unwrap();
+
+        assert_eq!(data, [1, 1, 3, 2, 2, 4, 3, 2, 4, 4, 2, 5]);
+    }
+
+    #[test]
+    fn rejects_unknown_filter() {
+        assert!(matches!(
+            unfilter(&mut [5, 0], 1, 1, 1),
+            Err(PngError::InvalidFilter(5))
+        ));
+    }
+
+    #[test]
+    fn decodes_true_color() {
+        let filtered = [0, 1, 2, 3, 4, 5, 6, 1, 7, 8, 9, 1, 1, 1];
+        let image = from_bytes(&png(2, 2, 2, false, &filtered)).unwrap();
+
+        assert_eq!(image.format, PixelFormat::Rgb8);
+        assert_eq!(image.bytes, [1, 2, 3, 4, 5, 6, 7, 8, 9, 8, 9, 10]);
+    }
+
+    #[test]
+    fn applies_palette_transparency() {
+        let palette = [255, 0, 0, 0, 255, 0, 0, 0, 255];
+        // Only the first two entries have an alpha, the third is opaque
+        let alphas = [0, 128];
+        let filtered = [0, 0, 1, 2];
+
+        let image = from_bytes(&png_with_chunks(
+            3,
+            1,
+            3,
+            false,
+            &[(b"PLTE", &palette), (b"tRNS", &alphas)],
+            &filtered,
+        ))
+        .unwrap();
+
+        assert_eq!(image.format, PixelFormat::Rgba8);
+        assert_eq!(image.bytes, [255, 0, 0, 0, 0, 255, 0, 128, 0, 0, 255, 255]);
+
+        // More alphas than palette entries
+        let result = from_bytes(&png_with_chunks(
+            3,
+            1,
+            3,
+            false,
+            &[(b"PLTE", &palette), (b"tRNS", &[0, 0, 0, 0])],
+            &filtered,
+        ));
+        assert!(matches!(result, Err(PngError::InvalidTransparency)));
+    }
+
+    #[test]
+    fn applies_color_key_transparency() {
+        let key = [0, 1, 0, 2, 0, 3];
+        let filtered = [0, 1, 2, 3, 1, 2, 4];
+
+        let image = from_bytes(&png_with_chunks(
+            2,
+            1,
+            2,
+            false,
+            &[(b"tRNS", &key)],
+            &filtered,
+        ))
+        .unwrap();
+
+        assert_eq!(image.format, PixelFormat::Rgba8);
+        assert_eq!(image.bytes, [1, 2, 3, 0, 1, 2, 4, 255]);
+    }
+
+    #[test]
+    fn decodes_adam7() {
+        // A 3x3 grayscale image holding 0 to 8 in reading order, split into
+        // its passes. Passes 2 and 3 are empty at this size
+        let filtered = [
+            0, 0, // Pass 1: (0, 0)
+            0, 2, // Pass 4: (2, 0)
+            0, 6, 8, // Pass 5: (0, 2) and (2, 2)
+            0, 1, 0, 7, // Pass 6: (1, 0), then (1, 2)
+            0, 3, 4, 5, // Pass 7: row 1
+        ];
+        let image = from_bytes(&png(3, 3, 0, true, &filtered)).unwrap();
+
+        let gray: Vec<u8> = image.bytes.chunks_exact(3).map(|rgb| rgb[0]).collect();
+        assert_eq!(gray, (0..9).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn rejects_huge_dimensions_without_allocating() {
+        let result = from_bytes(&png(0x7fff_ffff, 0x7fff_ffff, 0, false, &[0, 0]));
+        assert!(matches!(result, Err(PngError::DimensionsTooLarge)));
+
+        // Within the limit, but the file holds a single scanline
+        let result = from_bytes(&png(16384, 16384, 6, false, &[0, 0]));
+        assert!(matches!(result, Err(PngError::TruncatedImageData)));
+    }
+
+    #[test]
+    fn rejects_more_data_than_the_image_holds() {
+        let result = from_bytes(&png(1, 1, 0, false, &[0, 0, 0]));
+
+        assert!(matches!(
+            result,
+            Err(PngError::InflateError(InflateError::OutputTooLarge))
+        ));
+    }
+}
@@ -0,0 +1,521 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InflateError {
+    UnexpectedEof,
+    InvalidZlibHeader,
+    UnsupportedPresetDictionary,
+    InvalidBlockType,
+    InvalidStoredLength,
+    InvalidCodeLengths,
+    InvalidSymbol,
+    InvalidDistance,
+    ChecksumMismatch,
+    /// The stream holds more than the caller expects
+    OutputTooLarge,
+}
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+const MAX_BITS: usize = 15;
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    buffer: u32,
+    count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            position: 0,
+            buffer: 0,
+            count: 0,
+        }
+    }
+
+    fn bits(&mut self, n: u32) -> Result<u32, InflateError> {
+        while self.count < n {
+            let byte = *self
+                .data
+                .get(self.position)
+                .ok_or(InflateError::UnexpectedEof)?;
+
+            self.buffer |= (byte as u32) << self.count;
+            self.count += 8;
+            self.position += 1;
+        }
+
+        let value = self.buffer & ((1 << n) - 1);
+        self.buffer >>= n;
+        self.count -= n;
+
+        Ok(value)
+    }
+
+    /// Drops the remaining bits of the current byte
+    fn align(&mut self) {
+        self.buffer = 0;
+        self.count = 0;
+    }
+
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], InflateError> {
+        let bytes = self
+            .data
+            .get(self.position..(self.position + n))
+            .ok_or(InflateError::UnexpectedEof)?;
+
+        self.position += n;
+
+        Ok(bytes)
+    }
+}
+
+/// Canonical Huffman code, decoded one bit at a time
+struct Huffman {
+    counts: [u16; MAX_BITS + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Self, InflateError> {
+        let mut counts = [0u16; MAX_BITS + 1];
+
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+
+        // Reject over-subscribed codes. Incomplete codes are allowed, since
+        // encoders emit them for single-symbol distance trees
+        let mut left: i32 = 1;
+        for &count in &counts[1..] {
+            left <<= 1;
+            left -= count as i32;
+
+            if left < 0 {
+                return Err(InflateError::InvalidCodeLengths);
+            }
+        }
+
+        let mut offsets = [0u16; MAX_BITS + 1];
+        for length in 1..MAX_BITS {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+
+        Ok(Self { counts, symbols })
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, InflateError> {
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+
+        for &count in &self.counts[1..] {
+            code |= reader.bits(1)? as i32;
+            let count = count as i32;
+
+            if code - count < first {
+                return Ok(self.symbols[(index + (code - first)) as usize]);
+            }
+
+            index += count;
+            first += count;
+            first <<= 1;
+            code <<= 1;
+        }
+
+        Err(InflateError::InvalidSymbol)
+    }
+}
+
+/// Output of a stream, which may not grow past `max_size`. Memory is only
+/// reserved as data arrives, so that sizes taken from a file header can't
+/// allocate more than the stream actually holds
+struct Output {
+    bytes: Vec<u8>,
+    max_size: usize,
+}
+
+impl Output {
+    fn check_room(&self, additional: usize) -> Result<(), InflateError> {
+        match self.bytes.len().checked_add(additional) {
+            Some(size) if size <= self.max_size => Ok(()),
+            _ => Err(InflateError::OutputTooLarge),
+        }
+    }
+}
+
+fn inflate_stored(reader: &mut BitReader, output: &mut Output) -> Result<(), InflateError> {
+    reader.align();
+
+    let header = reader.bytes(4)?;
+    let length = u16::from_le_bytes([header[0], header[1]]);
+    let inverted_length = u16::from_le_bytes([header[2], header[3]]);
+
+    if length != !inverted_length {
+        return Err(InflateError::InvalidStoredLength);
+    }
+
+    output.check_room(length as usize)?;
+    output
+        .bytes
+        .extend_from_slice(reader.bytes(length as usize)?);
+
+    Ok(())
+}
+
+fn inflate_codes(
+    reader: &mut BitReader,
+    output: &mut Output,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> Result<(), InflateError> {
+    loop {
+        let symbol = literals.decode(reader)? as usize;
+
+        match symbol {
+            0..=255 => {
+                output.check_room(1)?;
+                output.bytes.push(symbol as u8);
+            }
+            256 => return Ok(()),
+            257..=285 => {
+                let symbol = symbol - 257;
+                let length = LENGTH_BASE[symbol] as usize
+                    + reader.bits(LENGTH_EXTRA[symbol] as u32)? as usize;
+
+                let symbol = distances.decode(reader)? as usize;
+                if symbol >= DISTANCE_BASE.len() {
+                    return Err(InflateError::InvalidSymbol);
+                }
+
+                let distance = DISTANCE_BASE[symbol] as usize
+                    + reader.bits(DISTANCE_EXTRA[symbol] as u32)? as usize;
+
+                if distance > output.bytes.len() {
+                    return Err(InflateError::InvalidDistance);
+                }
+
+                output.check_room(length)?;
+
+                // Copies may overlap with the bytes they produce
+                let start = output.bytes.len() - distance;
+                for i in 0..length {
+                    output.bytes.push(output.bytes[start + i]);
+                }
+            }
+            _ => return Err(InflateError::InvalidSymbol),
+        }
+    }
+}
+
+fn fixed_codes() -> Result<(Huffman, Huffman), InflateError> {
+    let mut lengths = [0u8; 288];
+
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+
+    Ok((Huffman::new(&lengths)?, Huffman::new(&[5; 30])?))
+}
+
+fn dynamic_codes(reader: &mut BitReader) -> Result<(Huffman, Huffman), InflateError> {
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_length_count = reader.bits(4)? as usize + 4;
+
+    if literal_count > 286 || distance_count > 30 {
+        return Err(InflateError::InvalidCodeLengths);
+    }
+
+    let mut code_lengths = [0u8; 19];
+    for &index in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_lengths[index] = reader.bits(3)? as u8;
+    }
+
+    let code_lengths = Huffman::new(&code_lengths)?;
+
+    let mut lengths = vec![0u8; literal_count + distance_count];
+    let mut index = 0;
+
+    while index < lengths.len() {
+        let symbol = code_lengths.decode(reader)?;
+
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *index
+                    .checked_sub(1)
+                    .and_then(|previous| lengths.get(previous))
+                    .ok_or(InflateError::InvalidCodeLengths)?;
+
+                (previous, 3 + reader.bits(2)? as usize)
+            }
+            17 => (0, 3 + reader.bits(3)? as usize),
+            18 => (0, 11 + reader.bits(7)? as usize),
+            _ => return Err(InflateError::InvalidCodeLengths),
+        };
+
+        if index + repeat > lengths.len() {
+            return Err(InflateError::InvalidCodeLengths);
+        }
+
+        lengths[index..(index + repeat)].fill(value);
+        index += repeat;
+    }
+
+    // A block without an end-of-block code could never terminate
+    if lengths[256] == 0 {
+        return Err(InflateError::InvalidCodeLengths);
+    }
+
+    Ok((
+        Huffman::new(&lengths[..literal_count])?,
+        Huffman::new(&lengths[literal_count..])?,
+    ))
+}
+
+fn inflate_blocks(reader: &mut BitReader, max_size: usize) -> Result<Vec<u8>, InflateError> {
+    let mut output = Output {
+        bytes: vec![],
+        max_size,
+    };
+
+    loop {
+        let last = reader.bits(1)? == 1;
+
+        match reader.bits(2)? {
+            0 => inflate_stored(reader, &mut output)?,
+            1 => {
+                let (literals, distances) = fixed_codes()?;
+                inflate_codes(reader, &mut output, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_codes(reader)?;
+                inflate_codes(reader, &mut output, &literals, &distances)?;
+            }
+            _ => return Err(InflateError::InvalidBlockType),
+        }
+
+        if last {
+            return Ok(output.bytes);
+        }
+    }
+}
+
+/// Decompresses a raw DEFLATE (RFC 1951) stream of at most `max_size` bytes
+pub fn inflate(data: &[u8], max_size: usize) -> Result<Vec<u8>, InflateError> {
+    inflate_blocks(&mut BitReader::new(data), max_size)
+}
+
+pub(super) fn adler32(data: &[u8]) -> u32 {
+    let mut a: u32 = 1;
+    let mut b: u32 = 0;
+
+    // 5552 is the largest block for which `b` cannot overflow
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+
+        a %= 65521;
+        b %= 65521;
+    }
+
+    (b << 16) | a
+}
+
+/// Decompresses a zlib (RFC 1950) stream of at most `max_size` bytes and
+/// verifies its checksum
+pub fn zlib_decompress(data: &[u8], max_size: usize) -> Result<Vec<u8>, InflateError> {
+    if data.len() < 6 {
+        return Err(InflateError::UnexpectedEof);
+    }
+
+    let (cmf, flg) = (data[0], data[1]);
+
+    if cmf & 0x0F != 8 || cmf >> 4 > 7 || u16::from_be_bytes([cmf, flg]) % 31 != 0 {
+        return Err(InflateError::InvalidZlibHeader);
+    }
+
+    if flg & 0x20 != 0 {
+        return Err(InflateError::UnsupportedPresetDictionary);
+    }
+
+    let mut reader = BitReader::new(&data[2..]);
+    let output = inflate_blocks(&mut reader, max_size)?;
+
+    // The checksum starts on the byte following the last block
+    reader.align();
+    let checksum = reader.bytes(4)?;
+    let checksum = u32::from_be_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]);
+
+    if adler32(&output) != checksum {
+        return Err(InflateError::ChecksumMismatch);
+    }
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Packs (value, bit count) pairs least significant bit first, like DEFLATE
+    fn pack_bits(fields: &[(u32, u32)]) -> Vec<u8> {
+        let mut bytes = vec![];
+        let mut bit = 0;
+
+        for &(value, count) in fields {
+            for i in 0..count {
+                if bit % 8 == 0 {
+                    bytes.push(0);
+                }
+
+                *bytes.last_mut().unwrap() |= (((value >> i) & 1) as u8) << (bit % 8);
+                bit += 1;
+            }
+        }
+
+        bytes
+    }
+
+    /// Text with skewed letter frequencies, which zlib encodes with dynamic codes
+    fn skewed_text() -> Vec<u8> {
+        let alphabet = b"eeeeeeeettaaoinsh";
+        let mut state: u64 = 1;
+
+        (0..256)
+            .map(|_| {
+                state = (state * 1103515245 + 12345) % (1 << 31);
+                alphabet[(state >> 16) as usize % alphabet.len()]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn inflates_stored_block() {
+        let data = [
+            0x78, 0x01, 0x01, 0x05, 0x00, 0xfa, 0xff, 0x68, 0x65, 0x6c, 0x6c, 0x6f, 0x06, 0x2c,
+            0x02, 0x15,
+        ];
+
+        assert_eq!(zlib_decompress(&data, 5).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn inflates_fixed_block() {
+        let data = [
+            0x78, 0x01, 0x4b, 0x4c, 0x4a, 0x4e, 0x84, 0x21, 0x00, 0x1d, 0xe0, 0x04, 0x99,
+        ];
+
+        assert_eq!(zlib_decompress(&data, 12).unwrap(), b"abcabcabcabc");
+    }
+
+    #[test]
+    fn inflates_dynamic_block() {
+        let data = [
+            0x78, 0xda, 0x25, 0x4f, 0x49, 0x0e, 0x00, 0x31, 0x08, 0x7a, 0x2b, 0x07, 0x12, 0xbd,
+            0xd8, 0x83, 0xfc, 0x3f, 0x03, 0x4e, 0x9b, 0x98, 0x56, 0xd9, 0xd4, 0x5b, 0xca, 0x17,
+            0x7c, 0x24, 0x51, 0x43, 0x2c, 0x07, 0xc2, 0x0e, 0xb5, 0x6a, 0xfa, 0x43, 0xd5, 0x82,
+            0x7c, 0x13, 0xdc, 0xf0, 0x0e, 0x3c, 0x79, 0xa1, 0xcc, 0x3d, 0x4a, 0xa4, 0x8c, 0x44,
+            0x6f, 0xdf, 0x98, 0xc1, 0xba, 0x4e, 0x30, 0x72, 0xdd, 0x3e, 0x66, 0x6f, 0xb1, 0xcf,
+            0xeb, 0x50, 0x60, 0xdb, 0x8f, 0x90, 0x5e, 0x19, 0x1c, 0x5e, 0x98, 0x65, 0x33, 0x3b,
+            0x09, 0xbf, 0x95, 0x6a, 0x2e, 0x24, 0xd7, 0x56, 0x12, 0x67, 0x5b, 0x13, 0x39, 0xb8,
+            0x67, 0x5e, 0x09, 0x3d, 0x4b, 0xbc, 0xe8, 0xfd, 0x0a, 0x51, 0x6a, 0x2a, 0x9d, 0x60,
+            0xcc, 0xf1, 0x4a, 0x1d, 0x85, 0xec, 0x9b, 0xdc, 0x75, 0x29, 0x3e, 0xb1, 0x37, 0x68,
+            0xe5,
+        ];
+
+        assert_eq!(data[2] >> 1 & 3, 2);
+        assert_eq!(zlib_decompress(&data, 256).unwrap(), skewed_text());
+    }
+
+    #[test]
+    fn caps_output_size() {
+        let data = [
+            0x78, 0x01, 0x4b, 0x4c, 0x4a, 0x4e, 0x84, 0x21, 0x00, 0x1d, 0xe0, 0x04, 0x99,
+        ];
+
+        assert_eq!(
+            zlib_decompress(&data, 11),
+            Err(InflateError::OutputTooLarge)
+        );
+    }
+
+    #[test]
+    fn rejects_over_subscribed_code_lengths() {
+        // Final dynamic block whose 19 code length codes are all 1 bit long
+        let mut fields = vec![(1, 1), (2, 2), (0, 5), (0, 5), (15, 4)];
+        fields.extend([(1, 3); 19]);
+
+        assert_eq!(
+            inflate(&pack_bits(&fields), 16),
+            Err(InflateError::InvalidCodeLengths)
+        );
+    }
+
+    #[test]
+    fn rejects_missing_end_of_block_code() {
+        // Code length codes 0 and 8 are 1 bit long. Literals 0 to 255 get
+        // length 8 and the end-of-block code gets 0
+        let mut fields = vec![(1, 1), (2, 2), (0, 5), (0, 5), (1, 4)];
+        fields.extend([(0, 3), (0, 3), (0, 3), (1, 3), (1, 3)]);
+        fields.extend([(1, 1); 256]);
+        fields.extend([(0, 1); 2]);
+
+        assert_eq!(
+            inflate(&pack_bits(&fields), 16),
+            Err(InflateError::InvalidCodeLengths)
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_blocks() {
+        assert_eq!(
+            inflate(&pack_bits(&[(1, 1), (3, 2)]), 16),
+            Err(InflateError::InvalidBlockType)
+        );
+        assert_eq!(
+            inflate(&[0x01, 0x05, 0x00, 0x00, 0x00], 16),
+            Err(InflateError::InvalidStoredLength)
+        );
+        // Fixed block copying from before the start of the output. Huffman
+        // codes are read most significant bit first, 0000001 is length 3
+        assert_eq!(
+            inflate(&pack_bits(&[(1, 1), (1, 2), (0b1000000, 7), (0, 5)]), 16),
+            Err(InflateError::InvalidDistance)
+        );
+    }
+}
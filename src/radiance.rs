@@ -0,0 +1,401 @@
+use std::{fs::File, io::Read, path::PathBuf};
+
+use crate::image::{Image, PixelFormat};
+
+/// A Radiance RGBE (`.hdr`) image
+#[derive(Debug)]
+pub struct Radiance {
+    pub image: Image,
+    /// Product of all `EXPOSURE` header values. Pixels have already been
+    /// multiplied by it when the file was written
+    pub exposure: f32,
+}
+
+#[derive(Debug)]
+pub enum RadianceError {
+    IoError(std::io::Error),
+    InvalidSignature,
+    TruncatedHeader,
+    UnsupportedFormat(String),
+    InvalidResolution(String),
+    UnsupportedOrientation(String),
+    ZeroDimensions,
+    SizeOverflow,
+    TruncatedScanline,
+    InvalidScanline,
+}
+
+impl From<std::io::Error> for RadianceError {
+    fn from(value: std::io::Error) -> Self {
+        Self::IoError(value)
+    }
+}
+
+#[derive(Debug)]
+struct RadianceHeader {
+    width: usize,
+    height: usize,
+    exposure: f32,
+    bottom_to_top: bool,
+}
+
+/// Reads a line without its newline, advancing `offset` past it
+fn read_line<'a>(data: &'a [u8], offset: &mut usize) -> Result<&'a str, RadianceError> {
+    let rest = data.get(*offset..).ok_or(RadianceError::TruncatedHeader)?;
+    let length = rest
+        .iter()
+        .position(|&b| b == b'\n')
+        .ok_or(RadianceError::TruncatedHeader)?;
+
+    *offset += length + 1;
+
+    std::str::from_utf8(&rest[..length]).map_err(|_| RadianceError::TruncatedHeader)
+}
+
+impl RadianceHeader {
+    fn from_bytes(data: &[u8], offset: &mut usize) -> Result<Self, RadianceError> {
+        let signature = read_line(data, offset)?;
+
+        if signature != "#?RADIANCE" && signature != "#?RGBE" {
+            return Err(RadianceError::InvalidSignature);
+        }
+
+        let mut exposure = 1.0;
+
+        // Variables end with an empty line
+        loop {
+            let line = read_line(data, offset)?.trim();
+
+            if line.is_empty() {
+                break;
+            }
+
+            if let Some(format) = line.strip_prefix("FORMAT=") {
+                if format != "32-bit_rle_rgbe" {
+                    return Err(RadianceError::UnsupportedFormat(format.to_string()));
+                }
+            } else if let Some(value) = line.strip_prefix("EXPOSURE=") {
+                if let Ok(value) = value.trim().parse::<f32>() {
+                    exposure *= value;
+                }
+            }
+        }
+
+        let resolution = read_line(data, offset)?;
+        let parts: Vec<_> = resolution.split_whitespace().collect();
+
+        if parts.len() != 4 {
+            return Err(RadianceError::InvalidResolution(resolution.to_string()));
+        }
+
+        let parse = |value: &str| {
+            value
+                .parse::<usize>()
+                .map_err(|_| RadianceError::InvalidResolution(resolution.to_string()))
+        };
+
+        let bottom_to_top = match (parts[0], parts[2]) {
+            ("-Y", "+X") => false,
+            ("+Y", "+X") => true,
+            _ => {
+                return Err(RadianceError::UnsupportedOrientation(
+                    resolution.to_string(),
+                ))
+            }
+        };
+
+        let height = parse(parts[1])?;
+        let width = parse(parts[3])?;
+
+        if width == 0 || height == 0 {
+            return Err(RadianceError::ZeroDimensions);
+        }
+
+        Ok(Self {
+            width,
+            height,
+            exposure,
+            bottom_to_top,
+        })
+    }
+}
+
+fn rgbe_to_rgb(rgbe: &[u8]) -> [f32; 3] {
+    if rgbe[3] == 0 {
+        return [0.0; 3];
+    }
+
+    // Mantissas are 8-bit fractions of the shared exponent
+    let scale = 2f32.powi(rgbe[3] as i32 - (128 + 8));
+
+    [
+        rgbe[0] as f32 * scale,
+        rgbe[1] as f32 * scale,
+        rgbe[2] as f32 * scale,
+    ]
+}
+
+/// Decodes a scanline stored with per-channel run-length encoding
+fn read_rle_scanline(
+    data: &[u8],
+    offset: &mut usize,
+    scanline: &mut [u8],
+    width: usize,
+) -> Result<(), RadianceError> {
+    for channel in 0..4 {
+        let mut x = 0;
+
+        while x < width {
+            let count = *data.get(*offset).ok_or(RadianceError::TruncatedScanline)?;
+            *offset += 1;
+
+            if count > 128 {
+                let count = count as usize - 128;
+                let value = *data.get(*offset).ok_or(RadianceError::TruncatedScanline)?;
+                *offset += 1;
+
+                if count == 0 || x + count > width {
+                    return Err(RadianceError::InvalidScanline);
+                }
+
+                for pixel in &mut scanline[(x * 4)..((x + count) * 4)].chunks_exact_mut(4) {
+                    pixel[channel] = value;
+                }
+
+                x += count;
+            } else {
+                let count = count as usize;
+                let values = data
+                    .get(*offset..(*offset + count))
+                    .ok_or(RadianceError::TruncatedScanline)?;
+                *offset += count;
+
+                if count == 0 || x + count > width {
+                    return Err(RadianceError::InvalidScanline);
+                }
+
+                for (pixel, &value) in scanline[(x * 4)..((x + count) * 4)]
+                    .chunks_exact_mut(4)
+                    .zip(values)
+                {
+                    pixel[channel] = value;
+                }
+
+                x += count;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Decodes a flat scanline, which may use the original encoding where a
+/// (1, 1, 1, n) pixel repeats the previous one
+fn read_flat_scanline(
+    data: &[u8],
+    offset: &mut usize,
+    scanline: &mut [u8],
+    width: usize,
+) -> Result<(), RadianceError> {
+    let mut x = 0;
+    let mut shift = 0;
+
+    while x < width {
+        let rgbe = data
+            .get(*offset..(*offset + 4))
+            .ok_or(RadianceError::TruncatedScanline)?;
+        *offset += 4;
+
+        if rgbe[..3] == [1, 1, 1] {
+            if x == 0 || shift > 24 {
+                return Err(RadianceError::InvalidScanline);
+            }
+
+            let count = (rgbe[3] as usize) << shift;
+            if x + count > width {
+                return Err(RadianceError::InvalidScanline);
+            }
+
+            let previous = [
+                scanline[x * 4 - 4],
+                scanline[x * 4 - 3],
+                scanline[x * 4 - 2],
+                scanline[x * 4 - 1],
+            ];
+
+            for pixel in scanline[(x * 4)..((x + count) * 4)].chunks_exact_mut(4) {
+                pixel.copy_from_slice(&previous);
+            }
+
+            x += count;
+            shift += 8;
+        } else {
+            scanline[(x * 4)..(x * 4 + 4)].copy_from_slice(rgbe);
+            x += 1;
+            shift = 0;
+        }
+    }
+
+    Ok(())
+}
+
+impl Radiance {
+    pub fn from_file<P: Into<PathBuf>>(path: P) -> Result<Self, RadianceError> {
+        let mut file = File::open(path.into())?;
+
+        let mut data = Vec::with_capacity(4096);
+
+        file.read_to_end(&mut data)?;
+
+        Self::from_bytes(&data)
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, RadianceError> {
+        let mut offset = 0;
+        let header = RadianceHeader::from_bytes(data, &mut offset)?;
+        let (width, height) = (header.width, header.height);
+
+        // Scanlines take at least 4 bytes, and pixels at least one unless the
+        // original run-length encoding is used. This bounds the allocations
+        // below for corrupt headers
+        if height > data.len() / 4 || width > data.len().max(0x7FFF) {
+            return Err(RadianceError::TruncatedScanline);
+        }
+
+        width
+            .checked_mul(height)
+            .and_then(|pixels| pixels.checked_mul(3 * 4))
+            .ok_or(RadianceError::SizeOverflow)?;
+
+        let mut components = vec![];
+        let mut scanline = vec![0u8; width * 4];
+
+        for _ in 0..height {
+            let marker = data.get(offset..(offset + 4));
+
+            // The run-length encoding is only used for widths in [8, 0x7FFF]
+            let is_rle = match marker {
+                Some(&[2, 2, high, low]) => {
+                    (8..0x8000).contains(&width) && (((high as usize) << 8) | low as usize) == width
+                }
+                _ => false,
+            };
+
+            if is_rle {
+                offset += 4;
+                read_rle_scanline(data, &mut offset, &mut scanline, width)?;
+            } else {
+                read_flat_scanline(data, &mut offset, &mut scanline, width)?;
+            }
+
+            components.extend(scanline.chunks_exact(4).flat_map(rgbe_to_rgb));
+        }
+
+        let mut image = Image::from_f32(width, height, PixelFormat::Rgb32F, &components);
+
+        if header.bottom_to_top {
+            image.flip_vertical();
+        }
+
+        Ok(Self {
+            image,
+            exposure: header.exposure,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(resolution: &str, pixels: &[u8]) -> Vec<u8> {
+        let mut data = format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\nEXPOSURE=2\n\n{resolution}\n")
+            .into_bytes();
+        data.extend_from_slice(pixels);
+        data
+    }
+
+    fn components(image: &Image) -> Vec<f32> {
+        image
+            .bytes
+            .chunks_exact(4)
+            .map(|bytes| f32::from_ne_bytes(bytes.try_into().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn converts_rgbe_to_float() {
+        assert_eq!(rgbe_to_rgb(&[128, 64, 0, 129]), [1.0, 0.5, 0.0]);
+        assert_eq!(rgbe_to_rgb(&[128, 128, 128, 128]), [0.5, 0.5, 0.5]);
+        assert_eq!(rgbe_to_rgb(&[255, 255, 255, 0]), [0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn decodes_rle_scanlines() {
+        let mut pixels = vec![2, 2, 0, 8];
+        // Red: one run of 8
+        pixels.extend_from_slice(&[128 + 8, 128]);
+        // Green: 8 literal values
+        pixels.extend_from_slice(&[8, 0, 16, 32, 48, 64, 80, 96, 112]);
+        // Blue: two runs of 4
+        pixels.extend_from_slice(&[128 + 4, 0, 128 + 4, 64]);
+        // Exponent: one run of 8
+        pixels.extend_from_slice(&[128 + 8, 129]);
+
+        let radiance = Radiance::from_bytes(&file("-Y 1 +X 8", &pixels)).unwrap();
+        let components = components(&radiance.image);
+
+        assert_eq!(radiance.exposure, 2.0);
+        assert_eq!((radiance.image.width, radiance.image.height), (8, 1));
+
+        for (x, rgb) in components.chunks_exact(3).enumerate() {
+            let blue = if x < 4 { 0.0 } else { 0.5 };
+            assert_eq!(rgb, [1.0, x as f32 * 0.125, blue]);
+        }
+    }
+
+    #[test]
+    fn decodes_old_style_runs_bottom_to_top() {
+        let pixels = [
+            // Bottom row: a pixel repeated 3 times by a (1, 1, 1, 3) pixel
+            128, 0, 0, 129, 1, 1, 1, 3, //
+            // Top row: flat pixels
+            0, 128, 0, 129, 0, 128, 0, 129, 0, 0, 128, 129, 0, 0, 128, 129,
+        ];
+
+        let radiance = Radiance::from_bytes(&file("+Y 2 +X 4", &pixels)).unwrap();
+        let components = components(&radiance.image);
+
+        assert_eq!(components[..3], [0.0, 1.0, 0.0]);
+        assert_eq!(components[9..12], [0.0, 0.0, 1.0]);
+        assert!(components[12..]
+            .chunks_exact(3)
+            .all(|rgb| rgb == [1.0, 0.0, 0.0]));
+    }
+
+    #[test]
+    fn rejects_broken_scanlines() {
+        // A run past the end of the scanline
+        let pixels = [2, 2, 0, 8, 128 + 9, 0];
+        assert!(matches!(
+            Radiance::from_bytes(&file("-Y 1 +X 8", &pixels)),
+            Err(RadianceError::InvalidScanline)
+        ));
+
+        // A repeat with no previous pixel
+        assert!(matches!(
+            Radiance::from_bytes(&file("-Y 1 +X 1", &[1, 1, 1, 1])),
+            Err(RadianceError::InvalidScanline)
+        ));
+
+        assert!(matches!(
+            Radiance::from_bytes(&file("-Y 1 +X 2", &[0, 0, 0, 0])),
+            Err(RadianceError::TruncatedScanline)
+        ));
+
+        assert!(matches!(
+            Radiance::from_bytes(&file("+X 1 -Y 1", &[0, 0, 0, 0])),
+            Err(RadianceError::UnsupportedOrientation(_))
+        ));
+    }
+}
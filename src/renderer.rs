@@ -1,11 +1,26 @@
-use self::{camera::Camera, mesh::Mesh, scene_object::SceneObject, shader::Shader};
+use self::{
+    scene_object::SceneObject,
+    shader::{Shader, UniformValue},
+    skybox::Skybox,
+    texture_unit::SamplerSource,
+};
 
+pub mod atlas;
 pub mod camera;
+pub mod cubemap;
+pub mod framebuffer;
+pub mod gl_state;
+pub mod material;
 pub mod math;
 pub mod mesh;
 pub mod scene_object;
 pub mod shader;
+pub mod skybox;
 pub mod texture;
+pub mod texture_array;
+pub mod texture_unit;
+pub mod uniform_buffer;
+pub mod vertex_layout;
 
 pub struct Renderer;
 
@@ -13,42 +28,86 @@ pub struct Renderer;
 pub enum Primitive {
     Triangles,
     Wireframe,
+    /// Every triangle as a patch of 3 control points, for shaders with
+    /// tessellation stages
+    Patches,
+}
+
+/// Uniforms set by the renderer are optional, a shader that declares them
+/// with another type is reported rather than drawn with garbage
+fn set_uniform<V: UniformValue + ?Sized>(shader: &Shader, name: &str, value: &V) {
+    if let Err(e) = shader.set_uniform(name, value) {
+        eprintln!("Invalid uniform: {e:?}");
+    }
 }
 
 impl Renderer {
-    pub fn draw_object(
+    /// Draws an object with its material. When its shader variant is invalid
+    /// or fails to compile, the base shader is used instead
+    pub fn draw(object: &SceneObject, primitive: Primitive) {
+        let material = &object.material;
+
+        let keywords: Vec<&str> = material.keywords.iter().map(String::as_str).collect();
+
+        // The object stays visible while the variant's source is being fixed
+        let mut shader = material.shader.borrow_mut();
+        let shader = match shader.variant(&keywords) {
+            Ok(shader) => shader,
+            Err(e) => {
+                eprintln!("Invalid shader variant {keywords:?}: {e:?}");
+                &*shader
+            }
+        };
+
+        for (name, value) in material.uniforms() {
+            set_uniform(shader, name, value);
+        }
+
+        let textures: Vec<_> = material.textures().collect();
+
+        Self::draw_object_with_textures(object, shader, primitive, &textures);
+    }
+
+    /// Draws an object with another shader than its material's, for debug views
+    pub fn draw_object(object: &SceneObject, shader: &Shader, primitive: Primitive) {
+        Self::draw_object_with_textures(object, shader, primitive, &[]);
+    }
+
+    /// Draws an object with textures bound to the units of the sampler
+    /// uniforms they are named after. Samplers the shader doesn't use are
+    /// skipped. The program, vertex array and textures stay bound after the
+    /// draw, so the next object using them doesn't bind them again
+    pub fn draw_object_with_textures(
         object: &SceneObject,
         shader: &Shader,
-        camera: &Camera,
         primitive: Primitive,
+        textures: &[(&str, &dyn SamplerSource)],
     ) {
         shader.bind();
         object.mesh().bind();
 
-        let model_matrix = object.transform.model_matrix();
-
-        let mvp = camera.view_projection_matrix() * model_matrix;
-
-        unsafe {
-            let mvp_location = shader.uniform_location("mvp");
-            let model_location = shader.uniform_location("modelMatrix");
-            let diffuse_location = shader.uniform_location("diffuseTex");
+        if cfg!(debug_assertions) {
+            shader.warn_attribute_mismatches(object.mesh());
+        }
 
-            if let Some(location) = mvp_location {
-                gl::UniformMatrix4fv(location.0, 1, gl::FALSE, &mvp as *const _ as _);
+        for &(name, texture) in textures {
+            if let Some(unit) = shader.sampler_unit(name) {
+                texture.bind_unit(unit);
             }
+        }
 
-            if let Some(location) = model_location {
-                gl::UniformMatrix4fv(location.0, 1, gl::FALSE, &model_matrix as *const _ as _);
-            }
+        let model_matrix = object.transform.model_matrix();
 
-            if let Some(location) = diffuse_location {
-                gl::Uniform1i(location.0, 0);
-            }
+        set_uniform(shader, "modelMatrix", &model_matrix);
 
+        unsafe {
             let mode = match primitive {
                 Primitive::Triangles => gl::TRIANGLES,
                 Primitive::Wireframe => gl::LINES,
+                Primitive::Patches => {
+                    gl::PatchParameteri(gl::PATCH_VERTICES, 3);
+                    gl::PATCHES
+                }
             };
 
             gl::DrawElements(
@@ -58,8 +117,30 @@ impl Renderer {
                 0 as _,
             );
         }
+    }
+
+    /// Draws the skybox on the far plane, so it should come after opaque
+    /// objects to only shade the pixels they left uncovered. The camera comes
+    /// from the `CameraBlock` of `FrameUniforms`
+    pub fn draw_skybox(skybox: &Skybox) {
+        skybox.shader.bind();
+        skybox.mesh.bind();
+
+        if let Some(unit) = skybox.shader.sampler_unit("environment") {
+            skybox.environment.bind_unit(unit);
+        }
+
+        unsafe {
+            gl::DepthFunc(gl::LEQUAL);
 
-        Mesh::unbind();
-        Shader::unbind();
+            gl::DrawElements(
+                gl::TRIANGLES,
+                skybox.mesh.index_count as i32,
+                gl::UNSIGNED_INT,
+                0 as _,
+            );
+
+            gl::DepthFunc(gl::LESS);
+        }
     }
 }
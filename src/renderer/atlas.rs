@@ -0,0 +1,268 @@
+use crate::image::{Image, PixelFormat};
+
+#[derive(Debug)]
+pub enum AtlasError {
+    Empty,
+    /// The images don't fit in a square of the maximum size
+    TooLarge,
+}
+
+/// Area of the atlas holding one of the source images, in pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AtlasRegion {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// Normalized texture coordinates of a region
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UvRect {
+    pub u0: f32,
+    pub v0: f32,
+    pub u1: f32,
+    pub v1: f32,
+}
+
+impl UvRect {
+    /// Maps coordinates of the source image into the atlas. They are clamped
+    /// to [0, 1] first, since a region can't repeat without sampling its
+    /// neighbours: textures meant to tile stretch their edges instead
+    pub fn map(&self, u: f32, v: f32) -> (f32, f32) {
+        let u = u.clamp(0.0, 1.0);
+        let v = v.clamp(0.0, 1.0);
+
+        (
+            self.u0 + u * (self.u1 - self.u0),
+            self.v0 + v * (self.v1 - self.v0),
+        )
+    }
+}
+
+/// Several images packed into one, with power-of-two dimensions
+#[derive(Debug)]
+pub struct Atlas {
+    pub image: Image,
+    /// One region per source image, in the same order
+    pub regions: Vec<AtlasRegion>,
+}
+
+/// Shelf packing: rectangles sorted by height fill rows left to right.
+/// Returns the position of every rectangle and the height used
+fn pack_shelves(sizes: &[(usize, usize)], width: usize) -> Option<(Vec<(usize, usize)>, usize)> {
+    let mut order: Vec<_> = (0..sizes.len()).collect();
+    order.sort_by(|&a, &b| {
+        sizes[b]
+            .1
+            .cmp(&sizes[a].1)
+            .then(sizes[b].0.cmp(&sizes[a].0))
+    });
+
+    let mut positions = vec![(0, 0); sizes.len()];
+    let (mut x, mut y, mut shelf_height) = (0, 0, 0);
+
+    for index in order {
+        let (w, h) = sizes[index];
+
+        if w > width {
+            return None;
+        }
+
+        if x + w > width {
+            y += shelf_height;
+            x = 0;
+            shelf_height = 0;
+        }
+
+        positions[index] = (x, y);
+        x += w;
+        shelf_height = shelf_height.max(h);
+    }
+
+    Some((positions, y + shelf_height))
+}
+
+impl Atlas {
+    /// Packs images into an atlas no larger than `max_size` on each side, with
+    /// `padding` pixels of repeated edges around each one so that filtering
+    /// doesn't bleed between neighbours. The atlas is always RGBA
+    pub fn from_images(
+        images: &[&Image],
+        padding: usize,
+        max_size: usize,
+    ) -> Result<Self, AtlasError> {
+        if images.is_empty() {
+            return Err(AtlasError::Empty);
+        }
+
+        let sizes: Vec<_> = images
+            .iter()
+            .map(|image| (image.width + 2 * padding, image.height + 2 * padding))
+            .collect();
+
+        let area: usize = sizes.iter().map(|(w, h)| w * h).sum();
+        let widest = sizes.iter().map(|&(w, _)| w).max().unwrap_or(1);
+
+        // Start from the smallest square that could hold everything
+        let mut width = widest.max((area as f64).sqrt().ceil() as usize);
+        width = width.next_power_of_two();
+
+        let (positions, used_height) = loop {
+            if width > max_size {
+                return Err(AtlasError::TooLarge);
+            }
+
+            match pack_shelves(&sizes, width) {
+                Some((positions, height)) if height <= width => break (positions, height),
+                _ => width *= 2,
+            }
+        };
+
+        let height = used_height.next_power_of_two();
+
+        let mut atlas = Image::new(
+            width,
+            height,
+            PixelFormat::Rgba8,
+            vec![0; width * height * 4],
+        );
+
+        let regions = images
+            .iter()
+            .zip(positions)
+            .map(|(image, (x, y))| {
+                let region = AtlasRegion {
+                    x: x + padding,
+                    y: y + padding,
+                    width: image.width,
+                    height: image.height,
+                };
+
+                Self::blit(&mut atlas, &image.to_rgba8(), region, padding);
+                region
+            })
+            .collect();
+
+        Ok(Self {
+            image: atlas,
+            regions,
+        })
+    }
+
+    /// Copies an RGBA image into its region, extending its edges into the
+    /// padding around it
+    fn blit(atlas: &mut Image, image: &Image, region: AtlasRegion, padding: usize) {
+        if image.width == 0 || image.height == 0 {
+            return;
+        }
+
+        let padded_width = region.width + 2 * padding;
+        let padded_height = region.height + 2 * padding;
+
+        for y in 0..padded_height {
+            let source_y = y.saturating_sub(padding).min(image.height - 1);
+            let row = atlas.row_mut(region.y - padding + y);
+
+            for x in 0..padded_width {
+                let source_x = x.saturating_sub(padding).min(image.width - 1);
+                let target = (region.x - padding + x) * 4;
+
+                row[target..(target + 4)].copy_from_slice(image.pixel(source_x, source_y));
+            }
+        }
+    }
+
+    pub fn uv_rect(&self, index: usize) -> UvRect {
+        let region = self.regions[index];
+        let width = self.image.width as f32;
+        let height = self.image.height as f32;
+
+        UvRect {
+            u0: region.x as f32 / width,
+            v0: region.y as f32 / height,
+            u1: (region.x + region.width) as f32 / width,
+            v1: (region.y + region.height) as f32 / height,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(width: usize, height: usize, value: u8) -> Image {
+        Image::new(
+            width,
+            height,
+            PixelFormat::Rgba8,
+            vec![value; width * height * 4],
+        )
+    }
+
+    #[test]
+    fn packs_tallest_first_in_shelves() {
+        let (positions, height) = pack_shelves(&[(2, 1), (3, 3), (2, 2), (4, 1)], 5).unwrap();
+
+        assert_eq!(positions, [(0, 4), (0, 0), (3, 0), (0, 3)]);
+        assert_eq!(height, 5);
+
+        assert_eq!(pack_shelves(&[(6, 1)], 5), None);
+    }
+
+    #[test]
+    fn packs_padded_images_without_overlap() {
+        let images = [solid(4, 4, 10), solid(2, 3, 20), solid(3, 1, 30)];
+        let atlas = Atlas::from_images(&images.iter().collect::<Vec<_>>(), 1, 64).unwrap();
+
+        assert!(atlas.image.width.is_power_of_two());
+        assert!(atlas.image.height.is_power_of_two());
+
+        for (i, a) in atlas.regions.iter().enumerate() {
+            assert_eq!((a.width, a.height), (images[i].width, images[i].height));
+            assert_eq!(atlas.image.pixel(a.x, a.y)[0], images[i].pixel(0, 0)[0]);
+            // The padding repeats the edge
+            assert_eq!(
+                atlas.image.pixel(a.x - 1, a.y - 1)[0],
+                images[i].pixel(0, 0)[0]
+            );
+
+            for b in &atlas.regions[(i + 1)..] {
+                let apart = a.x + a.width + 1 < b.x - 1
+                    || b.x + b.width + 1 < a.x - 1
+                    || a.y + a.height + 1 < b.y - 1
+                    || b.y + b.height + 1 < a.y - 1;
+                let touching = a.x + a.width + 1 == b.x - 1
+                    || b.x + b.width + 1 == a.x - 1
+                    || a.y + a.height + 1 == b.y - 1
+                    || b.y + b.height + 1 == a.y - 1;
+
+                assert!(apart || touching, "{a:?} overlaps {b:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_images_over_the_maximum_size() {
+        assert!(matches!(
+            Atlas::from_images(&[&solid(9, 9, 0)], 0, 8),
+            Err(AtlasError::TooLarge)
+        ));
+        assert!(matches!(
+            Atlas::from_images(&[], 0, 8),
+            Err(AtlasError::Empty)
+        ));
+    }
+
+    #[test]
+    fn maps_uvs_into_the_region() {
+        let atlas = Atlas::from_images(&[&solid(4, 4, 0), &solid(4, 4, 1)], 0, 16).unwrap();
+        let rect = atlas.uv_rect(1);
+
+        assert_eq!(rect.u1 - rect.u0, 4.0 / atlas.image.width as f32);
+        assert_eq!(rect.map(0.0, 0.0), (rect.u0, rect.v0));
+        assert_eq!(rect.map(1.0, 1.0), (rect.u1, rect.v1));
+        // Tiling coordinates are clamped to the region
+        assert_eq!(rect.map(2.5, -1.0), (rect.u1, rect.v0));
+    }
+}
@@ -95,7 +95,7 @@ impl Camera {
         }
     }
 
-    fn projection_matrix(&self) -> Mat4 {
+    pub fn projection_matrix(&self) -> Mat4 {
         self.projection_matrix
     }
 
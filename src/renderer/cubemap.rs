@@ -0,0 +1,188 @@
+use std::f32::consts::PI;
+
+use gl::types::GLenum;
+
+use crate::image::Image;
+
+use super::{
+    gl_state,
+    math::vec::Vec3,
+    texture::{upload_format, ColorSpace, FloatPrecision},
+    texture_unit::{SamplerSource, TextureUnit},
+};
+
+#[derive(Debug)]
+pub enum CubemapError {
+    NotSquare,
+    FaceSizeMismatch,
+    FormatMismatch,
+}
+
+/// Faces in `GL_TEXTURE_CUBE_MAP_POSITIVE_X + i` order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CubemapFace {
+    PositiveX,
+    NegativeX,
+    PositiveY,
+    NegativeY,
+    PositiveZ,
+    NegativeZ,
+}
+
+impl CubemapFace {
+    pub const ALL: [Self; 6] = [
+        Self::PositiveX,
+        Self::NegativeX,
+        Self::PositiveY,
+        Self::NegativeY,
+        Self::PositiveZ,
+        Self::NegativeZ,
+    ];
+
+    /// Direction through a point of the face, with `u` and `v` in [-1, 1]
+    /// and `v` growing downwards, following the GL cube map convention
+    pub fn direction(&self, u: f32, v: f32) -> Vec3 {
+        match self {
+            Self::PositiveX => Vec3(1.0, -v, -u),
+            Self::NegativeX => Vec3(-1.0, -v, u),
+            Self::PositiveY => Vec3(u, 1.0, v),
+            Self::NegativeY => Vec3(u, -1.0, -v),
+            Self::PositiveZ => Vec3(u, -v, 1.0),
+            Self::NegativeZ => Vec3(-u, -v, -1.0),
+        }
+    }
+}
+
+pub struct Cubemap {
+    raw: u32,
+    size: usize,
+}
+
+impl Cubemap {
+    /// Creates a cubemap from six square images of the same size and format,
+    /// in `CubemapFace::ALL` order
+    pub fn from_faces(faces: &[Image; 6], precision: FloatPrecision) -> Result<Self, CubemapError> {
+        let size = faces[0].width;
+
+        for face in faces {
+            if face.width != face.height {
+                return Err(CubemapError::NotSquare);
+            }
+            if face.width != size {
+                return Err(CubemapError::FaceSizeMismatch);
+            }
+            if face.format != faces[0].format {
+                return Err(CubemapError::FormatMismatch);
+            }
+        }
+
+        // Environments hold colors, 8-bit faces are always sRGB
+        let (internal_format, format, typ) =
+            upload_format(faces[0].format, precision, ColorSpace::Srgb);
+
+        unsafe {
+            let mut raw = 0;
+            gl::GenTextures(1, &mut raw);
+
+            gl_state::bind_texture(TextureUnit::FIRST, gl::TEXTURE_CUBE_MAP, raw);
+
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+
+            for (i, face) in faces.iter().enumerate() {
+                let packed;
+                let face = if face.is_packed() {
+                    face
+                } else {
+                    packed = face.to_packed();
+                    &packed
+                };
+
+                gl::TexImage2D(
+                    gl::TEXTURE_CUBE_MAP_POSITIVE_X + i as u32,
+                    0,
+                    internal_format as _,
+                    size as _,
+                    size as _,
+                    0,
+                    format,
+                    typ,
+                    face.bytes.as_ptr() as _,
+                );
+            }
+
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+
+            gl::TexParameteri(
+                gl::TEXTURE_CUBE_MAP,
+                gl::TEXTURE_MIN_FILTER,
+                gl::LINEAR as i32,
+            );
+            gl::TexParameteri(
+                gl::TEXTURE_CUBE_MAP,
+                gl::TEXTURE_MAG_FILTER,
+                gl::LINEAR as i32,
+            );
+
+            for wrap in [gl::TEXTURE_WRAP_S, gl::TEXTURE_WRAP_T, gl::TEXTURE_WRAP_R] {
+                gl::TexParameteri(gl::TEXTURE_CUBE_MAP, wrap, gl::CLAMP_TO_EDGE as i32);
+            }
+
+            gl_state::bind_texture(TextureUnit::FIRST, gl::TEXTURE_CUBE_MAP, 0);
+
+            Ok(Self { raw, size })
+        }
+    }
+
+    /// Projects an equirectangular (latitude/longitude) image onto six faces
+    /// of `size` pixels
+    pub fn from_equirectangular(image: &Image, size: usize, precision: FloatPrecision) -> Self {
+        let channels = image.format.channels();
+
+        let faces = CubemapFace::ALL.map(|face| {
+            let mut components = Vec::with_capacity(size * size * channels);
+
+            for y in 0..size {
+                for x in 0..size {
+                    let u = 2.0 * (x as f32 + 0.5) / size as f32 - 1.0;
+                    let v = 2.0 * (y as f32 + 0.5) / size as f32 - 1.0;
+                    let direction = face.direction(u, v).normalize();
+
+                    let longitude = direction.0.atan2(-direction.2);
+                    let latitude = direction.1.clamp(-1.0, 1.0).acos();
+
+                    let sample = image.sample(0.5 + longitude / (2.0 * PI), latitude / PI);
+                    components.extend_from_slice(&sample[..channels]);
+                }
+            }
+
+            Image::from_components(size, size, image.format, &components)
+        });
+
+        // Faces are square and share the source format
+        Self::from_faces(&faces, precision).unwrap()
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+}
+
+impl SamplerSource for Cubemap {
+    fn target(&self) -> GLenum {
+        gl::TEXTURE_CUBE_MAP
+    }
+
+    fn raw_texture(&self) -> u32 {
+        self.raw
+    }
+}
+
+impl Drop for Cubemap {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteTextures(1, &self.raw);
+        }
+
+        gl_state::forget_texture(self.raw);
+    }
+}
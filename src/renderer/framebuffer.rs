@@ -0,0 +1,324 @@
+use gl::types::GLenum;
+
+use crate::image::{Image, PixelFormat};
+
+use super::{
+    gl_state,
+    texture::Filter,
+    texture_unit::{SamplerSource, TextureUnit},
+};
+
+#[derive(Debug)]
+pub enum FramebufferError {
+    ZeroDimensions,
+    /// Status returned by `glCheckFramebufferStatus`
+    Incomplete(GLenum),
+}
+
+/// Storage of the color attachment
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorFormat {
+    Rgba8,
+    Srgb8Alpha8,
+    Rgba16F,
+}
+
+impl ColorFormat {
+    fn raw(&self) -> GLenum {
+        match self {
+            Self::Rgba8 => gl::RGBA8,
+            Self::Srgb8Alpha8 => gl::SRGB8_ALPHA8,
+            Self::Rgba16F => gl::RGBA16F,
+        }
+    }
+
+    fn is_float(&self) -> bool {
+        matches!(self, Self::Rgba16F)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FramebufferOptions {
+    pub color: ColorFormat,
+    /// Adds a 24-bit depth and 8-bit stencil attachment
+    pub depth: bool,
+}
+
+impl Default for FramebufferOptions {
+    fn default() -> Self {
+        Self {
+            color: ColorFormat::Srgb8Alpha8,
+            depth: true,
+        }
+    }
+}
+
+/// Offscreen render target with a color texture and an optional depth/stencil
+/// renderbuffer
+pub struct Framebuffer {
+    raw: u32,
+    color: u32,
+    depth: Option<u32>,
+    width: usize,
+    height: usize,
+    options: FramebufferOptions,
+}
+
+impl Framebuffer {
+    pub fn new(
+        width: usize,
+        height: usize,
+        options: FramebufferOptions,
+    ) -> Result<Self, FramebufferError> {
+        let mut framebuffer = unsafe {
+            let mut raw = 0;
+            gl::GenFramebuffers(1, &mut raw);
+
+            let mut color = 0;
+            gl::GenTextures(1, &mut color);
+
+            let depth = options.depth.then(|| {
+                let mut depth = 0;
+                gl::GenRenderbuffers(1, &mut depth);
+                depth
+            });
+
+            Self {
+                raw,
+                color,
+                depth,
+                width: 0,
+                height: 0,
+                options,
+            }
+        };
+
+        framebuffer.resize(width, height)?;
+
+        Ok(framebuffer)
+    }
+
+    /// Reallocates the attachments. Their previous contents are lost. The new
+    /// size is kept even when the framebuffer turns out incomplete, since
+    /// the attachments have been reallocated at it
+    pub fn resize(&mut self, width: usize, height: usize) -> Result<(), FramebufferError> {
+        if width == 0 || height == 0 {
+            return Err(FramebufferError::ZeroDimensions);
+        }
+
+        unsafe {
+            gl_state::bind_texture(TextureUnit::FIRST, gl::TEXTURE_2D, self.color);
+
+            gl::TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                self.options.color.raw() as _,
+                width as _,
+                height as _,
+                0,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                std::ptr::null(),
+            );
+
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+
+            gl_state::bind_texture(TextureUnit::FIRST, gl::TEXTURE_2D, 0);
+
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.raw);
+            gl::FramebufferTexture2D(
+                gl::FRAMEBUFFER,
+                gl::COLOR_ATTACHMENT0,
+                gl::TEXTURE_2D,
+                self.color,
+                0,
+            );
+
+            if let Some(depth) = self.depth {
+                gl::BindRenderbuffer(gl::RENDERBUFFER, depth);
+                gl::RenderbufferStorage(
+                    gl::RENDERBUFFER,
+                    gl::DEPTH24_STENCIL8,
+                    width as _,
+                    height as _,
+                );
+                gl::BindRenderbuffer(gl::RENDERBUFFER, 0);
+
+                gl::FramebufferRenderbuffer(
+                    gl::FRAMEBUFFER,
+                    gl::DEPTH_STENCIL_ATTACHMENT,
+                    gl::RENDERBUFFER,
+                    depth,
+                );
+            }
+
+            self.width = width;
+            self.height = height;
+
+            let status = gl::CheckFramebufferStatus(gl::FRAMEBUFFER);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+
+            if status != gl::FRAMEBUFFER_COMPLETE {
+                return Err(FramebufferError::Incomplete(status));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn options(&self) -> FramebufferOptions {
+        self.options
+    }
+
+    /// Renders into this framebuffer and sets the viewport to cover it
+    pub fn bind(&self) {
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.raw);
+            gl::Viewport(0, 0, self.width as _, self.height as _);
+        }
+    }
+
+    /// Renders into the window again. The viewport is set to the given window
+    /// size
+    pub fn bind_default(width: usize, height: usize) {
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+            gl::Viewport(0, 0, width as _, height as _);
+        }
+    }
+
+    /// Reads the color attachment back, top row first
+    pub fn read_pixels(&self) -> Image {
+        unsafe {
+            Self::read_framebuffer_pixels(self.raw, self.width, self.height, self.options.color)
+        }
+    }
+
+    /// Reads the window contents back, top row first
+    pub fn read_default_pixels(width: usize, height: usize) -> Image {
+        unsafe { Self::read_framebuffer_pixels(0, width, height, ColorFormat::Rgba8) }
+    }
+
+    unsafe fn read_framebuffer_pixels(
+        raw: u32,
+        width: usize,
+        height: usize,
+        color: ColorFormat,
+    ) -> Image {
+        let (format, typ) = if color.is_float() {
+            (PixelFormat::Rgba32F, gl::FLOAT)
+        } else {
+            (PixelFormat::Rgba8, gl::UNSIGNED_BYTE)
+        };
+
+        let mut bytes = vec![0u8; width * height * format.bytes_per_pixel()];
+
+        gl::BindFramebuffer(gl::READ_FRAMEBUFFER, raw);
+        if raw != 0 {
+            gl::ReadBuffer(gl::COLOR_ATTACHMENT0);
+        }
+        gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+
+        gl::ReadPixels(
+            0,
+            0,
+            width as _,
+            height as _,
+            gl::RGBA,
+            typ,
+            bytes.as_mut_ptr() as _,
+        );
+
+        gl::PixelStorei(gl::PACK_ALIGNMENT, 4);
+        gl::BindFramebuffer(gl::READ_FRAMEBUFFER, 0);
+
+        // GL rows start at the bottom
+        let mut image = Image::new(width, height, format, bytes);
+        image.flip_vertical();
+        image
+    }
+
+    /// Copies the color (and depth, when both have it) contents into
+    /// `target`, scaling them to its size
+    pub fn blit_to(&self, target: &Framebuffer, filter: Filter) {
+        let mut mask = gl::COLOR_BUFFER_BIT;
+
+        // Depth can only be blitted without scaling or filtering
+        if self.depth.is_some()
+            && target.depth.is_some()
+            && (self.width, self.height) == (target.width, target.height)
+        {
+            mask |= gl::DEPTH_BUFFER_BIT | gl::STENCIL_BUFFER_BIT;
+        }
+
+        unsafe { self.blit(target.raw, target.width, target.height, mask, filter) }
+    }
+
+    /// Copies the color contents into the window, scaling them to its size
+    pub fn blit_to_default(&self, width: usize, height: usize, filter: Filter) {
+        unsafe { self.blit(0, width, height, gl::COLOR_BUFFER_BIT, filter) }
+    }
+
+    unsafe fn blit(&self, target: u32, width: usize, height: usize, mask: u32, filter: Filter) {
+        let filter = match filter {
+            Filter::Nearest => gl::NEAREST,
+            Filter::Linear if mask == gl::COLOR_BUFFER_BIT => gl::LINEAR,
+            Filter::Linear => gl::NEAREST,
+        };
+
+        gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.raw);
+        gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, target);
+
+        gl::BlitFramebuffer(
+            0,
+            0,
+            self.width as _,
+            self.height as _,
+            0,
+            0,
+            width as _,
+            height as _,
+            mask,
+            filter,
+        );
+
+        gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+    }
+}
+
+/// Samples the color attachment, e.g. in a post-processing pass
+impl SamplerSource for Framebuffer {
+    fn target(&self) -> GLenum {
+        gl::TEXTURE_2D
+    }
+
+    fn raw_texture(&self) -> u32 {
+        self.color
+    }
+}
+
+impl Drop for Framebuffer {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteFramebuffers(1, &self.raw);
+
+            gl_state::forget_texture(self.color);
+            gl::DeleteTextures(1, &self.color);
+
+            if let Some(depth) = self.depth {
+                gl::DeleteRenderbuffers(1, &depth);
+            }
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use std::{cell::RefCell, collections::HashMap};
+
+use gl::types::GLenum;
+
+use super::texture_unit::TextureUnit;
+
+/// What the tracker knows is bound on the context. `None` is unknown, so
+/// the next call goes through whatever it asks for
+#[derive(Debug, Default)]
+struct GlState {
+    program: Option<u32>,
+    vertex_array: Option<u32>,
+    active_unit: Option<u32>,
+    /// Texture bound to each (unit, target)
+    textures: HashMap<(u32, GLenum), u32>,
+    capabilities: HashMap<GLenum, bool>,
+}
+
+thread_local! {
+    // GL contexts are current on one thread at a time
+    static STATE: RefCell<GlState> = RefCell::default();
+}
+
+/// Stores `value` in `slot`, telling whether it changed
+fn replace<T: PartialEq>(slot: &mut Option<T>, value: T) -> bool {
+    if slot.as_ref() == Some(&value) {
+        return false;
+    }
+
+    *slot = Some(value);
+    true
+}
+
+pub fn use_program(program: u32) {
+    STATE.with_borrow_mut(|state| {
+        if replace(&mut state.program, program) {
+            unsafe { gl::UseProgram(program) }
+        }
+    });
+}
+
+pub fn bind_vertex_array(vertex_array: u32) {
+    STATE.with_borrow_mut(|state| {
+        if replace(&mut state.vertex_array, vertex_array) {
+            unsafe { gl::BindVertexArray(vertex_array) }
+        }
+    });
+}
+
+pub fn active_texture(unit: TextureUnit) {
+    STATE.with_borrow_mut(|state| {
+        if replace(&mut state.active_unit, unit.index()) {
+            unsafe { gl::ActiveTexture(gl::TEXTURE0 + unit.index()) }
+        }
+    });
+}
+
+/// Leaves `unit` active even when the texture was already bound to it, since
+/// callers go on to edit the texture through its target
+pub fn bind_texture(unit: TextureUnit, target: GLenum, texture: u32) {
+    active_texture(unit);
+
+    let bound = STATE.with_borrow(|state| state.textures.get(&(unit.index(), target)).copied());
+
+    if bound == Some(texture) {
+        return;
+    }
+
+    unsafe { gl::BindTexture(target, texture) }
+
+    STATE.with_borrow_mut(|state| state.textures.insert((unit.index(), target), texture));
+}
+
+/// `glEnable` or `glDisable`
+pub fn set_capability(capability: GLenum, enabled: bool) {
+    STATE.with_borrow_mut(|state| {
+        if state.capabilities.get(&capability) == Some(&enabled) {
+            return;
+        }
+
+        unsafe {
+            match enabled {
+                true => gl::Enable(capability),
+                false => gl::Disable(capability),
+            }
+        }
+
+        state.capabilities.insert(capability, enabled);
+    });
+}
+
+/// A deleted program stays in use until another one is, but its name may
+/// be handed out again
+pub fn forget_program(program: u32) {
+    STATE.with_borrow_mut(|state| {
+        if state.program == Some(program) {
+            state.program = None;
+        }
+    });
+}
+
+/// Deleting a bound vertex array binds 0 in its place
+pub fn forget_vertex_array(vertex_array: u32) {
+    STATE.with_borrow_mut(|state| {
+        if state.vertex_array == Some(vertex_array) {
+            state.vertex_array = Some(0);
+        }
+    });
+}
+
+/// Deleting a texture binds 0 to every unit it was bound to
+pub fn forget_texture(texture: u32) {
+    STATE.with_borrow_mut(|state| {
+        for bound in state.textures.values_mut() {
+            if *bound == texture {
+                *bound = 0;
+            }
+        }
+    });
+}
+
+/// For code that changed bindings with raw GL calls
+pub fn invalidate() {
+    STATE.set(GlState::default());
+}
@@ -0,0 +1,78 @@
+use std::{cell::RefCell, rc::Rc};
+
+use super::{
+    shader::{Shader, UniformValue},
+    texture_unit::SamplerSource,
+};
+
+/// How an object looks: the shader variant it's drawn with, and the
+/// uniforms and textures that go with it
+pub struct Material {
+    /// Shared between materials, and editable for hot reloading
+    pub shader: Rc<RefCell<Shader>>,
+    /// Keywords of the shader variant to draw with
+    pub keywords: Vec<String>,
+    uniforms: Vec<(String, Box<dyn UniformValue>)>,
+    textures: Vec<(String, Rc<dyn SamplerSource>)>,
+}
+
+impl Material {
+    pub fn new(shader: Rc<RefCell<Shader>>) -> Self {
+        Self {
+            shader,
+            keywords: vec![],
+            uniforms: vec![],
+            textures: vec![],
+        }
+    }
+
+    /// Sets the value uploaded to `name` on every draw
+    pub fn set_uniform<V: UniformValue + 'static>(&mut self, name: &str, value: V) {
+        let value: Box<dyn UniformValue> = Box::new(value);
+
+        match self
+            .uniforms
+            .iter_mut()
+            .find(|(uniform, _)| uniform == name)
+        {
+            Some((_, previous)) => *previous = value,
+            None => self.uniforms.push((name.to_string(), value)),
+        }
+    }
+
+    /// Binds `texture` to the sampler uniform `name`
+    pub fn set_texture(&mut self, name: &str, texture: Rc<dyn SamplerSource>) {
+        match self
+            .textures
+            .iter_mut()
+            .find(|(sampler, _)| sampler == name)
+        {
+            Some((_, previous)) => *previous = texture,
+            None => self.textures.push((name.to_string(), texture)),
+        }
+    }
+
+    pub fn set_keyword(&mut self, keyword: &str, enabled: bool) {
+        let position = self.keywords.iter().position(|k| k == keyword);
+
+        match (position, enabled) {
+            (None, true) => self.keywords.push(keyword.to_string()),
+            (Some(position), false) => {
+                self.keywords.remove(position);
+            }
+            _ => {}
+        }
+    }
+
+    pub fn uniforms(&self) -> impl Iterator<Item = (&str, &dyn UniformValue)> {
+        self.uniforms
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_ref()))
+    }
+
+    pub fn textures(&self) -> impl Iterator<Item = (&str, &dyn SamplerSource)> {
+        self.textures
+            .iter()
+            .map(|(name, texture)| (name.as_str(), texture.as_ref()))
+    }
+}
@@ -151,6 +151,35 @@ impl Mat4 {
     }
 }
 
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Mat3 {
+    pub x_axis: Vec3,
+    pub y_axis: Vec3,
+    pub z_axis: Vec3,
+}
+
+impl Mat3 {
+    pub const IDENTITY: Self = Self::from_cols(
+        Vec3(1.0, 0.0, 0.0),
+        Vec3(0.0, 1.0, 0.0),
+        Vec3(0.0, 0.0, 1.0),
+    );
+
+    pub const fn from_cols(x_axis: Vec3, y_axis: Vec3, z_axis: Vec3) -> Self {
+        Self {
+            x_axis,
+            y_axis,
+            z_axis,
+        }
+    }
+
+    /// Upper-left 3x3 part, dropping the translation
+    pub fn from_mat4(mat: &Mat4) -> Self {
+        Self::from_cols(mat.x_axis.xyz(), mat.y_axis.xyz(), mat.z_axis.xyz())
+    }
+}
+
 impl std::ops::Mul<Mat4> for Mat4 {
     type Output = Mat4;
 
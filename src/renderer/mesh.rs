@@ -1,13 +1,58 @@
-use std::{collections::HashMap, mem::size_of};
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::c_void,
+    mem::{size_of, size_of_val},
+    ops::Range,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use gl::types::GLenum;
 
 use crate::wavefront::{self, Face, FaceAttribute};
 
-use super::math::{
-    boundingbox::BoundingBox,
-    vec::{Vec3, Vec4},
+use super::{
+    atlas::UvRect,
+    gl_state,
+    math::{
+        boundingbox::BoundingBox,
+        vec::{Vec3, Vec4},
+    },
+    vertex_layout::{ComponentType, VertexAttribute, VertexChannel, VertexLayout},
 };
 
-#[derive(Clone, Debug)]
+/// How often the buffers of a mesh are expected to change, a hint for where
+/// the driver keeps them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BufferUsage {
+    /// Uploaded once
+    #[default]
+    Static,
+    /// Updated now and then, drawn many times in between
+    Dynamic,
+    /// Updated about as often as it is drawn
+    Stream,
+}
+
+impl BufferUsage {
+    pub fn raw(&self) -> GLenum {
+        match self {
+            Self::Static => gl::STATIC_DRAW,
+            Self::Dynamic => gl::DYNAMIC_DRAW,
+            Self::Stream => gl::STREAM_DRAW,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum MeshUpdateError {
+    /// The update ends past the end of the buffer, only updates starting at
+    /// 0 can grow it
+    OutOfRange { first: u32, count: u32, len: u32 },
+}
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug)]
 pub struct Mesh {
     pub vao: u32,
     pub vbo: u32,
@@ -15,92 +60,59 @@ pub struct Mesh {
     pub vertex_count: u32,
     pub index_count: u32,
     pub uv_count: u32,
+    layout: VertexLayout,
+    usage: BufferUsage,
+    id: u64,
 }
 
+/// Per-vertex channels, each either empty or covering every vertex
 pub struct MeshData {
     pub positions: Vec<f32>,
     pub normals: Vec<f32>,
     pub colors: Vec<f32>,
     pub uvs: Vec<f32>,
+    /// xyz, and the handedness of the bitangent in w
+    pub tangents: Vec<f32>,
+    pub uvs2: Vec<f32>,
+    /// 4 per vertex, as floats like the other channels
+    pub bone_indices: Vec<f32>,
+    pub bone_weights: Vec<f32>,
     pub indices: Vec<u32>,
 }
 
 impl Mesh {
+    /// Uploads `data` with the layout it provides
     pub fn new(data: &MeshData) -> Self {
+        Self::with_layout(data, data.layout())
+    }
+
+    /// Uploads the channels of `data` that `layout` asks for, converted to
+    /// its component types
+    pub fn with_layout(data: &MeshData, layout: VertexLayout) -> Self {
+        Self::with_usage(data, layout, BufferUsage::Static)
+    }
+
+    /// For meshes updated in place with `update_vertices` and
+    /// `update_indices`
+    pub fn with_usage(data: &MeshData, layout: VertexLayout, usage: BufferUsage) -> Self {
         let mut vao = 0;
         let mut vbo = 0;
         let mut ebo = 0;
 
         unsafe {
             gl::GenVertexArrays(1, &mut vao);
-            gl::BindVertexArray(vao);
+            gl_state::bind_vertex_array(vao);
 
             gl::GenBuffers(1, &mut vbo);
             gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
 
-            let mut vbo_data = vec![];
-
-            // Layout: Position (vec3), Color (vec3), UV (vec2)
-            let mut positions_iterator = data.positions.chunks(3).map(|chunk| {
-                let x = chunk.get(0).unwrap_or(&0.0);
-                let y = chunk.get(1).unwrap_or(&0.0);
-                let z = chunk.get(2).unwrap_or(&0.0);
-                Vec3(*x, *y, *z)
-            });
-
-            let mut colors_iterator = data.colors.chunks(3).map(|chunk| {
-                let x = chunk.get(0).unwrap_or(&0.0);
-                let y = chunk.get(1).unwrap_or(&0.0);
-                let z = chunk.get(2).unwrap_or(&0.0);
-                Vec3(*x, *y, *z)
-            });
-
-            let mut normals_iterator = data.normals.chunks(3).map(|chunk| {
-                let x = chunk.get(0).unwrap_or(&0.0);
-                let y = chunk.get(1).unwrap_or(&0.0);
-                let z = chunk.get(2).unwrap_or(&0.0);
-                Vec3(*x, *y, *z)
-            });
-
-            let mut uvs_iterator = data.uvs.chunks(2).map(|chunk| {
-                let x = chunk.get(0).unwrap_or(&0.0);
-                let y = chunk.get(1).unwrap_or(&0.0);
-                (*x, *y)
-            });
-
-            loop {
-                let position = positions_iterator.next();
-
-                let position = match position {
-                    Some(position) => position,
-                    None => break,
-                };
-
-                let color = colors_iterator.next().unwrap_or(Vec3(1.0, 1.0, 1.0));
-                let uv = uvs_iterator.next().unwrap_or((0.0, 0.0));
-                let normal = normals_iterator.next().unwrap_or(Vec3(0.0, 0.0, 0.0));
-
-                vbo_data.push(position.0);
-                vbo_data.push(position.1);
-                vbo_data.push(position.2);
-
-                vbo_data.push(normal.0);
-                vbo_data.push(normal.1);
-                vbo_data.push(normal.2);
-
-                vbo_data.push(color.0);
-                vbo_data.push(color.1);
-                vbo_data.push(color.2);
-
-                vbo_data.push(uv.0);
-                vbo_data.push(uv.1);
-            }
+            let vbo_data = layout.encode(data);
 
             gl::BufferData(
                 gl::ARRAY_BUFFER,
-                (vbo_data.len() * size_of::<f32>()) as _,
+                vbo_data.len() as _,
                 vbo_data.as_ptr() as _,
-                gl::STATIC_DRAW,
+                usage.raw(),
             );
 
             gl::GenBuffers(1, &mut ebo);
@@ -110,60 +122,18 @@ impl Mesh {
                 gl::ELEMENT_ARRAY_BUFFER,
                 (data.indices.len() * size_of::<u32>()) as _,
                 data.indices.as_ptr() as _,
-                gl::STATIC_DRAW,
+                usage.raw(),
             );
 
-            gl::VertexAttribPointer(
-                0,
-                3,
-                gl::FLOAT,
-                gl::FALSE,
-                (11 * size_of::<f32>()) as _,
-                std::ptr::null(),
-            );
-
-            let normal_offset = (3 * std::mem::size_of::<f32>()) as _;
-            gl::VertexAttribPointer(
-                1,
-                3,
-                gl::FLOAT,
-                gl::FALSE,
-                (11 * size_of::<f32>()) as _,
-                normal_offset,
-            );
-
-            let color_offset = (6 * std::mem::size_of::<f32>()) as _;
-            gl::VertexAttribPointer(
-                2,
-                3,
-                gl::FLOAT,
-                gl::FALSE,
-                (11 * size_of::<f32>()) as _,
-                color_offset,
-            );
-
-            let uv_offset = (9 * std::mem::size_of::<f32>()) as _;
-            gl::VertexAttribPointer(
-                3,
-                2,
-                gl::FLOAT,
-                gl::FALSE,
-                (11 * size_of::<f32>()) as _,
-                uv_offset,
-            );
-
-            gl::EnableVertexAttribArray(0);
-            gl::EnableVertexAttribArray(1);
-            gl::EnableVertexAttribArray(2);
-            gl::EnableVertexAttribArray(3);
+            layout.apply();
 
-            let vertex_count = data.positions.len() as u32;
+            let vertex_count = data.vertex_count() as u32;
             let index_count = data.indices.len() as u32;
             let uv_count = data.uvs.len() as u32;
 
             gl::BindBuffer(gl::ARRAY_BUFFER, 0);
 
-            gl::BindVertexArray(0);
+            gl_state::bind_vertex_array(0);
 
             gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, 0);
 
@@ -174,21 +144,132 @@ impl Mesh {
                 vertex_count,
                 index_count,
                 uv_count,
+                layout,
+                usage,
+                id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
             }
         }
     }
 
-    pub fn bind(&self) {
+    /// Unique to this mesh for the whole run, unlike its GL names
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn usage(&self) -> BufferUsage {
+        self.usage
+    }
+
+    /// Overwrites vertices from `first_vertex` on with those of `data`,
+    /// encoded with the layout of the mesh. An update that starts at 0 and
+    /// covers every vertex orphans the buffer instead, which also resizes it
+    pub fn update_vertices(
+        &mut self,
+        first_vertex: u32,
+        data: &MeshData,
+    ) -> Result<(), MeshUpdateError> {
+        let count = data.vertex_count() as u32;
+        let orphan = check_range(first_vertex, count, self.vertex_count)?;
+
+        let bytes = self.layout.encode(data);
+        let offset = first_vertex as usize * self.layout.stride();
+
         unsafe {
-            gl::BindVertexArray(self.vao);
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+            upload(
+                gl::ARRAY_BUFFER,
+                offset,
+                bytes.as_ptr() as _,
+                bytes.len(),
+                orphan.then_some(self.usage),
+            );
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+        }
+
+        if orphan {
+            self.vertex_count = count;
+            self.uv_count = data.uvs.len() as u32;
         }
+
+        Ok(())
     }
 
-    pub fn unbind() {
+    /// Same as `update_vertices`, for indices. Resizing also changes how many
+    /// indices are drawn
+    pub fn update_indices(
+        &mut self,
+        first_index: u32,
+        indices: &[u32],
+    ) -> Result<(), MeshUpdateError> {
+        let count = indices.len() as u32;
+        let orphan = check_range(first_index, count, self.index_count)?;
+
+        // The element array binding belongs to the vertex array
+        gl_state::bind_vertex_array(self.vao);
+
         unsafe {
-            gl::BindVertexArray(0);
+            upload(
+                gl::ELEMENT_ARRAY_BUFFER,
+                first_index as usize * size_of::<u32>(),
+                indices.as_ptr() as _,
+                size_of_val(indices),
+                orphan.then_some(self.usage),
+            );
+        }
+
+        if orphan {
+            self.index_count = count;
         }
+
+        Ok(())
+    }
+
+    pub fn layout(&self) -> &VertexLayout {
+        &self.layout
     }
+
+    /// What the vertex buffer feeds to the vertex shader
+    pub fn attributes(&self) -> &[VertexAttribute] {
+        self.layout.attributes()
+    }
+
+    pub fn bind(&self) {
+        gl_state::bind_vertex_array(self.vao);
+    }
+
+    pub fn unbind() {
+        gl_state::bind_vertex_array(0);
+    }
+}
+
+/// Tells whether an update of `count` elements from `first` replaces a whole
+/// buffer of `len` elements
+fn check_range(first: u32, count: u32, len: u32) -> Result<bool, MeshUpdateError> {
+    if first == 0 && count >= len {
+        return Ok(true);
+    }
+
+    match first.checked_add(count) {
+        Some(end) if end <= len => Ok(false),
+        _ => Err(MeshUpdateError::OutOfRange { first, count, len }),
+    }
+}
+
+/// Writes `size` bytes at `offset` in the buffer bound to `target`.
+/// Orphaning first gives the buffer new storage of exactly `size` bytes, so
+/// the driver doesn't wait for draws still reading the old contents
+unsafe fn upload(
+    target: GLenum,
+    offset: usize,
+    data: *const c_void,
+    size: usize,
+    orphan: Option<BufferUsage>,
+) {
+    if let Some(usage) = orphan {
+        gl::BufferData(target, size as _, std::ptr::null(), usage.raw());
+    }
+
+    gl::BufferSubData(target, offset as _, size as _, data);
 }
 
 impl Drop for Mesh {
@@ -198,10 +279,10 @@ impl Drop for Mesh {
             gl::DeleteBuffers(1, &self.vbo);
             gl::DeleteBuffers(1, &self.ebo);
 
-            gl::BindVertexArray(0);
             gl::BindBuffer(gl::ARRAY_BUFFER, 0);
-            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, 0);
         }
+
+        gl_state::forget_vertex_array(self.vao);
     }
 }
 
@@ -341,6 +422,10 @@ impl From<wavefront::Obj> for MeshData {
             colors: vec![],
             normals,
             uvs,
+            tangents: vec![],
+            uvs2: vec![],
+            bone_indices: vec![],
+            bone_weights: vec![],
         }
     }
 }
@@ -353,9 +438,131 @@ impl MeshData {
             uvs: vec![],
             colors: vec![],
             normals: vec![],
+            tangents: vec![],
+            uvs2: vec![],
+            bone_indices: vec![],
+            bone_weights: vec![],
+        }
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.positions.len() / 3
+    }
+
+    pub fn channel(&self, channel: VertexChannel) -> &[f32] {
+        match channel {
+            VertexChannel::Position => &self.positions,
+            VertexChannel::Normal => &self.normals,
+            VertexChannel::Color => &self.colors,
+            VertexChannel::Uv => &self.uvs,
+            VertexChannel::Tangent => &self.tangents,
+            VertexChannel::Uv2 => &self.uvs2,
+            VertexChannel::BoneIndices => &self.bone_indices,
+            VertexChannel::BoneWeights => &self.bone_weights,
+        }
+    }
+
+    fn channel_mut(&mut self, channel: VertexChannel) -> &mut Vec<f32> {
+        match channel {
+            VertexChannel::Position => &mut self.positions,
+            VertexChannel::Normal => &mut self.normals,
+            VertexChannel::Color => &mut self.colors,
+            VertexChannel::Uv => &mut self.uvs,
+            VertexChannel::Tangent => &mut self.tangents,
+            VertexChannel::Uv2 => &mut self.uvs2,
+            VertexChannel::BoneIndices => &mut self.bone_indices,
+            VertexChannel::BoneWeights => &mut self.bone_weights,
         }
     }
 
+    /// A compact layout of the channels that cover every vertex, so absent
+    /// ones cost no memory and read their default value instead. Colors and bone weights fit in normalized bytes,
+    /// bone indices in plain bytes
+    pub fn layout(&self) -> VertexLayout {
+        let vertex_count = self.vertex_count();
+
+        let attributes = VertexChannel::ALL
+            .into_iter()
+            .filter(|&channel| {
+                vertex_count > 0 && self.channel(channel).len() >= vertex_count * channel.size()
+            })
+            .map(|channel| {
+                let (component_type, normalized) = match channel {
+                    VertexChannel::Color | VertexChannel::BoneWeights => {
+                        (ComponentType::UnsignedByte, true)
+                    }
+                    VertexChannel::BoneIndices => (ComponentType::UnsignedByte, false),
+                    _ => (ComponentType::Float, false),
+                };
+
+                VertexAttribute::new(channel, channel.size(), component_type, normalized)
+            })
+            .collect();
+
+        VertexLayout::new(attributes)
+    }
+
+    /// Moves the UVs of the triangles in `indices[range]` into an atlas
+    /// region. Vertices shared with triangles outside the range are duplicated
+    /// so that those keep their coordinates. UVs outside [0, 1] are clamped,
+    /// see `UvRect::map`
+    pub fn remap_uvs(&mut self, range: Range<usize>, rect: &UvRect) {
+        let shared: HashSet<u32> = self.indices[..range.start]
+            .iter()
+            .chain(&self.indices[range.end..])
+            .copied()
+            .collect();
+
+        let mut remapped: HashMap<u32, u32> = HashMap::new();
+
+        for i in range {
+            let vertex = self.indices[i];
+
+            let target = match remapped.get(&vertex) {
+                Some(&target) => target,
+                None => {
+                    let target = if shared.contains(&vertex) {
+                        self.duplicate_vertex(vertex)
+                    } else {
+                        vertex
+                    };
+
+                    let uv = target as usize * 2;
+                    if let Some(&[u, v]) = self.uvs.get(uv..(uv + 2)) {
+                        let (u, v) = rect.map(u, v);
+                        self.uvs[uv] = u;
+                        self.uvs[uv + 1] = v;
+                    }
+
+                    remapped.insert(vertex, target);
+                    target
+                }
+            };
+
+            self.indices[i] = target;
+        }
+    }
+
+    /// Appends a copy of a vertex and returns its index
+    fn duplicate_vertex(&mut self, vertex: u32) -> u32 {
+        let index = self.positions.len() / 3;
+
+        for channel in VertexChannel::ALL {
+            let size = channel.size();
+            let data = self.channel_mut(channel);
+
+            // Attributes that don't cover every vertex are left alone
+            if data.len() != index * size {
+                continue;
+            }
+
+            let start = vertex as usize * size;
+            data.extend_from_within(start..(start + size));
+        }
+
+        index as u32
+    }
+
     fn get_lowest(vertex: &Vec3, lowest: &Option<Vec3>) -> Vec3 {
         let lowest = match lowest {
             Some(lowest) => lowest,
@@ -396,29 +603,161 @@ impl MeshData {
         Some(BoundingBox::new(lowest?, highest?))
     }
 
-	#[rustfmt::skip]
+    #[rustfmt::skip]
     pub fn axes() -> Self {
-		let mut mesh_data = MeshData::new();
+        let mut mesh_data = MeshData::new();
+
+        mesh_data.positions = vec![
+            0.0, 0.0, 0.0, 1000.0, 0.0, 0.0,
+            0.0, 0.0, 0.0, 0.0, 1000.0, 0.0,
+            0.0, 0.0, 0.0, 0.0, 0.0, 1000.0,
+        ];
+
+        mesh_data.colors = vec![
+            1.0, 0.0, 0.0, 1.0, 0.0, 0.0,
+            0.0, 1.0, 0.0, 0.0, 1.0, 0.0,
+            0.0, 0.0, 1.0, 0.0, 0.0, 1.0,
+        ];
+
+        mesh_data.indices = vec![
+            0, 1, 2,
+            3, 4, 5,
+            6, 7, 8,
+        ];
+
+        mesh_data
+    }
 
-		mesh_data.positions = vec![
-			0.0, 0.0, 0.0, 1000.0, 0.0, 0.0,
-			0.0, 0.0, 0.0, 0.0, 1000.0, 0.0,
-			0.0, 0.0, 0.0, 0.0, 0.0, 1000.0,
-		];
+    /// Cube of half-size 1 with faces wound to be visible from the inside
+    #[rustfmt::skip]
+    pub fn inward_cube() -> Self {
+        let mut mesh_data = MeshData::new();
+
+        mesh_data.positions = vec![
+            -1.0, -1.0, -1.0,  1.0, -1.0, -1.0,
+            -1.0,  1.0, -1.0,  1.0,  1.0, -1.0,
+            -1.0, -1.0,  1.0,  1.0, -1.0,  1.0,
+            -1.0,  1.0,  1.0,  1.0,  1.0,  1.0,
+        ];
+
+        mesh_data.indices = vec![
+            0, 1, 3, 0, 3, 2, // -Z
+            5, 4, 6, 5, 6, 7, // +Z
+            4, 0, 2, 4, 2, 6, // -X
+            1, 5, 7, 1, 7, 3, // +X
+            1, 0, 4, 1, 4, 5, // -Y
+            2, 3, 7, 2, 7, 6, // +Y
+        ];
+
+        mesh_data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two triangles sharing the edge 1-2
+    fn quad() -> MeshData {
+        let mut data = MeshData::new();
+        data.positions = vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 1.0, 1.0, 0.0];
+        data.colors = [1.0, 0.0, 0.0].repeat(4);
+        data.uvs = vec![0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 1.0];
+        data.indices = vec![0, 1, 2, 2, 1, 3];
+        data
+    }
 
-		mesh_data.colors = vec![
-			1.0, 0.0, 0.0, 1.0, 0.0, 0.0,
-       		0.0, 1.0, 0.0, 0.0, 1.0, 0.0,
-        	0.0, 0.0, 1.0, 0.0, 0.0, 1.0,
-		];
+    const RECT: UvRect = UvRect {
+        u0: 0.5,
+        v0: 0.0,
+        u1: 1.0,
+        v1: 0.5,
+    };
+
+    #[test]
+    fn remaps_uvs_of_unshared_vertices_in_place() {
+        let mut data = quad();
+        data.remap_uvs(0..6, &RECT);
+
+        assert_eq!(data.vertex_count(), 4);
+        assert_eq!(data.indices, [0, 1, 2, 2, 1, 3]);
+        assert_eq!(data.uvs, [0.5, 0.0, 1.0, 0.0, 0.5, 0.5, 1.0, 0.5]);
+    }
 
-		mesh_data.indices = vec![
-			0, 1, 2,
-			3, 4, 5,
-			6, 7, 8,
-		];
+    #[test]
+    fn lays_out_only_the_channels_a_mesh_has() {
+        let mut data = quad();
+        // Not one per vertex
+        data.normals = vec![0.0, 0.0, 1.0];
+
+        let channels: Vec<_> = data
+            .layout()
+            .attributes()
+            .iter()
+            .map(VertexAttribute::channel)
+            .collect();
+
+        assert_eq!(
+            channels,
+            [
+                VertexChannel::Position,
+                VertexChannel::Color,
+                VertexChannel::Uv
+            ]
+        );
+    }
 
+    #[test]
+    fn duplicates_vertices_shared_outside_the_range() {
+        let mut data = quad();
+        data.remap_uvs(3..6, &RECT);
+
+        // 2 and 1 are shared with the first triangle, 3 isn't
+        assert_eq!(data.indices, [0, 1, 2, 4, 5, 3]);
+        assert_eq!(data.vertex_count(), 6);
+        assert_eq!(data.colors.len(), 18);
+        assert_eq!(data.positions[12..], [0.0, 1.0, 0.0, 1.0, 0.0, 0.0]);
+
+        // The first triangle keeps its coordinates
+        assert_eq!(data.uvs[..6], [0.0, 0.0, 1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(data.uvs[6..8], [1.0, 0.5]);
+        assert_eq!(data.uvs[8..], [0.5, 0.5, 1.0, 0.0]);
+    }
 
-		mesh_data
-	}
+    #[test]
+    fn orphans_updates_covering_the_buffer() {
+        assert!(check_range(0, 8, 8).unwrap());
+        // Growing the buffer
+        assert!(check_range(0, 12, 8).unwrap());
+    }
+
+    #[test]
+    fn writes_sub_ranges_in_place() {
+        assert!(!check_range(0, 4, 8).unwrap());
+        assert!(!check_range(2, 6, 8).unwrap());
+        assert!(!check_range(8, 0, 8).unwrap());
+    }
+
+    #[test]
+    fn rejects_updates_past_the_end() {
+        assert!(matches!(
+            check_range(4, 5, 8),
+            Err(MeshUpdateError::OutOfRange {
+                first: 4,
+                count: 5,
+                len: 8
+            })
+        ));
+        assert!(check_range(9, 0, 8).is_err());
+        assert!(check_range(u32::MAX, 2, 8).is_err());
+    }
+
+    #[test]
+    fn leaves_partial_channels_alone_when_duplicating() {
+        let mut data = quad();
+        data.normals = vec![0.0, 0.0, 1.0];
+        data.remap_uvs(3..6, &RECT);
+
+        assert_eq!(data.normals.len(), 3);
+    }
 }
@@ -1,18 +1,28 @@
 use std::rc::Rc;
 
-use super::{math::transform::Transform, mesh::Mesh};
+use super::{material::Material, math::transform::Transform, mesh::Mesh};
 
 pub struct SceneObject {
     mesh: Rc<Mesh>,
+    pub material: Material,
     pub transform: Transform,
 }
 
 impl SceneObject {
-    pub fn new(mesh: Rc<Mesh>, transform: Transform) -> Self {
-        Self { mesh, transform }
+    pub fn new(mesh: Rc<Mesh>, material: Material, transform: Transform) -> Self {
+        Self {
+            mesh,
+            material,
+            transform,
+        }
     }
 
     pub fn mesh(&self) -> &Mesh {
         self.mesh.as_ref()
     }
+
+    /// `None` while the mesh is shared with other objects
+    pub fn mesh_mut(&mut self) -> Option<&mut Mesh> {
+        Rc::get_mut(&mut self.mesh)
+    }
 }
@@ -1,32 +1,82 @@
 use std::{
-    collections::HashMap,
+    cell::RefCell,
+    collections::{HashMap, HashSet},
     ffi::CString,
-    fs::File,
-    io::{self, BufRead, BufReader},
-    path::PathBuf,
+    fs, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
 };
 
 use gl::types::{GLchar, GLenum, GLint, GLsizei, GLuint};
 
 use crate::utils::NonNegativeI32;
 
-#[derive(Debug)]
+use self::{
+    binary_cache::ProgramKey,
+    include::{ExpandedSource, IncludeErrorDetail},
+    line_map::LineMap,
+};
+
+pub use self::uniform::{UniformElement, UniformError, UniformValue};
+
+use super::{
+    gl_state,
+    mesh::Mesh,
+    texture_unit::{TextureUnit, TextureUnitAllocator},
+    uniform_buffer::UniformBlockBinding,
+    vertex_layout::{VertexAttribute, VertexChannel},
+};
+
+mod binary_cache;
+mod include;
+mod line_map;
+mod uniform;
+
+#[derive(Debug, Clone)]
 pub struct ShaderSource {
     pub vertex_source: String,
     pub fragment_source: String,
     pub geometry_source: Option<String>,
+    pub tess_control_source: Option<String>,
+    pub tess_evaluation_source: Option<String>,
+    /// Files the source was read from, including the included ones
+    pub files: Vec<PathBuf>,
+    /// Where the lines of each stage come from, to report compile errors
+    /// against the original files
+    pub vertex_lines: LineMap,
+    pub fragment_lines: LineMap,
+    pub geometry_lines: LineMap,
+    pub tess_control_lines: LineMap,
+    pub tess_evaluation_lines: LineMap,
+    /// Declared with `#pragma keywords`, each variant defines some of them
+    pub keywords: Vec<String>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ShaderUniformType {
     Int,
+    IVec2,
+    IVec3,
+    IVec4,
     Uint,
+    UVec2,
+    UVec3,
+    UVec4,
+    Bool,
     Float,
     Vec2,
     Vec3,
     Vec4,
+    Mat2,
+    Mat3,
     Mat4,
     Sampler2D,
+    Sampler3D,
+    Sampler2DArray,
+    Sampler2DShadow,
+    SamplerCube,
+    /// A GL type without a variant, kept so that the uniform is still listed
+    Unknown(GLenum),
 }
 
 impl ShaderUniformType {
@@ -37,12 +87,36 @@ impl ShaderUniformType {
             gl::FLOAT_VEC3 => Self::Vec3,
             gl::FLOAT_VEC4 => Self::Vec4,
             gl::INT => Self::Int,
+            gl::INT_VEC2 => Self::IVec2,
+            gl::INT_VEC3 => Self::IVec3,
+            gl::INT_VEC4 => Self::IVec4,
             gl::UNSIGNED_INT => Self::Uint,
+            gl::UNSIGNED_INT_VEC2 => Self::UVec2,
+            gl::UNSIGNED_INT_VEC3 => Self::UVec3,
+            gl::UNSIGNED_INT_VEC4 => Self::UVec4,
+            gl::BOOL => Self::Bool,
+            gl::FLOAT_MAT2 => Self::Mat2,
+            gl::FLOAT_MAT3 => Self::Mat3,
             gl::FLOAT_MAT4 => Self::Mat4,
             gl::SAMPLER_2D => Self::Sampler2D,
-            other => panic!("Unknown shader param type: {:?}", other),
+            gl::SAMPLER_3D => Self::Sampler3D,
+            gl::SAMPLER_2D_ARRAY => Self::Sampler2DArray,
+            gl::SAMPLER_2D_SHADOW => Self::Sampler2DShadow,
+            gl::SAMPLER_CUBE => Self::SamplerCube,
+            other => Self::Unknown(other),
         };
     }
+
+    pub fn is_sampler(&self) -> bool {
+        matches!(
+            self,
+            Self::Sampler2D
+                | Self::Sampler3D
+                | Self::Sampler2DArray
+                | Self::Sampler2DShadow
+                | Self::SamplerCube
+        )
+    }
 }
 
 #[derive(Debug)]
@@ -50,12 +124,97 @@ pub struct ShaderUniformInfo {
     name: String,
     location: Option<NonNegativeI32>,
     typ: ShaderUniformType,
+    /// Number of elements, 1 unless the uniform is an array
+    size: usize,
+    /// Texture unit the sampler reads from, assigned when linking
+    unit: Option<TextureUnit>,
+}
+
+/// An active vertex shader input. Attribute types are the same GL enums as
+/// uniform types
+#[derive(Debug)]
+pub struct ShaderAttributeInfo {
+    name: String,
+    location: u32,
+    typ: ShaderUniformType,
+}
+
+impl ShaderAttributeInfo {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn location(&self) -> u32 {
+        self.location
+    }
+
+    pub fn typ(&self) -> ShaderUniformType {
+        self.typ
+    }
+}
+
+/// A difference between what the vertex shader reads and what a mesh provides
+#[derive(Debug)]
+pub enum AttributeMismatch {
+    /// Nothing feeds that location, and it isn't a `VertexChannel` with a
+    /// default value, so the shader reads (0, 0, 0, 1)
+    Missing { name: String, location: u32 },
+    TypeMismatch {
+        name: String,
+        location: u32,
+        expected: ShaderUniformType,
+        got: ShaderUniformType,
+    },
+}
+
+#[derive(Debug)]
+pub struct ShaderUniformBlockInfo {
+    index: u32,
+    /// `GL_UNIFORM_BLOCK_DATA_SIZE`
+    size: usize,
+    /// Binding point, for the blocks shared by every shader
+    binding: Option<UniformBlockBinding>,
+}
+
+impl ShaderUniformBlockInfo {
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn binding(&self) -> Option<UniformBlockBinding> {
+        self.binding
+    }
 }
 
 #[derive(Debug)]
 pub struct Shader {
     program: RawProgram,
     uniforms: HashMap<String, ShaderUniformInfo>,
+    uniform_blocks: HashMap<String, ShaderUniformBlockInfo>,
+    /// Sorted by location
+    attributes: Vec<ShaderAttributeInfo>,
+    /// Ids of the meshes already checked against `attributes`. GL names
+    /// are reused once deleted, so they can't tell meshes apart
+    checked_meshes: RefCell<HashSet<u64>>,
+    origin: Option<ShaderOrigin>,
+    /// Kept to compile variants, when the source declares keywords
+    source: Option<ShaderSource>,
+    /// Programs compiled for a set of keywords, in declaration order. `None`
+    /// if that variant failed to compile
+    variants: HashMap<Vec<String>, Option<Shader>>,
+}
+
+/// Where a shader loaded with `Shader::from_file` came from, to reload it
+#[derive(Debug)]
+struct ShaderOrigin {
+    path: PathBuf,
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
 }
 
 #[derive(Debug)]
@@ -77,6 +236,7 @@ struct RawProgram {
 impl Drop for RawProgram {
     fn drop(&mut self) {
         unsafe { gl::DeleteProgram(self.raw) }
+        gl_state::forget_program(self.raw);
     }
 }
 
@@ -86,6 +246,8 @@ enum ShaderPragma {
     Vertex,
     Fragment,
     Geometry,
+    TessControl,
+    TessEvaluation,
 }
 
 #[derive(Debug)]
@@ -95,10 +257,29 @@ pub enum ShaderError {
     VertexCompileError(String),
     FragmentCompileError(String),
     GeometryCompileError(String),
+    TessControlCompileError(String),
+    TessEvaluationCompileError(String),
     EntryPointError(String),
     LinkError(String),
     MissingVertexEntryPoint,
     MissingFragmentEntryPoint,
+    /// A tessellation control stage needs an evaluation stage after it
+    MissingTessEvaluationEntryPoint,
+    /// More samplers than `GL_MAX_COMBINED_TEXTURE_IMAGE_UNITS`
+    TooManySamplers(usize),
+    /// A keyword the source doesn't declare with `#pragma keywords`
+    UnknownKeyword(String),
+    /// A shared uniform block declared with another layout than the Rust one
+    UniformBlockMismatch {
+        name: String,
+        expected: usize,
+        got: usize,
+    },
+    IncludeError {
+        file: PathBuf,
+        line: usize,
+        detail: IncludeErrorDetail,
+    },
 }
 
 impl From<io::Error> for ShaderError {
@@ -135,34 +316,54 @@ fn replace_entry_with_main(line: &str) -> Result<String, ShaderError> {
         line += format!("{} ", part).as_str();
     }
 
+    // Keeps one output line per source line for the line maps
+    line.push('\n');
+
     Ok(line)
 }
 
 impl ShaderSource {
     pub fn from_file<P: Into<PathBuf>>(path: P) -> Result<Self, ShaderError> {
-        let file = File::open(path.into())?;
-        let reader = BufReader::new(file);
+        let source = ExpandedSource::from_file(&path.into())?;
 
         let mut is_vertex_entry_point = false;
         let mut is_fragment_entry_point = false;
         let mut is_geometry_entry_point = false;
+        let mut is_tess_control_entry_point = false;
+        let mut is_tess_evaluation_entry_point = false;
 
         struct UnprocessedShaderSource {
             vertex_source: String,
             fragment_source: String,
             geometry_source: String,
+            tess_control_source: String,
+            tess_evaluation_source: String,
+            vertex_lines: LineMap,
+            fragment_lines: LineMap,
+            geometry_lines: LineMap,
+            tess_control_lines: LineMap,
+            tess_evaluation_lines: LineMap,
         }
 
         let mut shader_source = UnprocessedShaderSource {
             vertex_source: String::new(),
             fragment_source: String::new(),
             geometry_source: String::new(),
+            tess_control_source: String::new(),
+            tess_evaluation_source: String::new(),
+            vertex_lines: LineMap::default(),
+            fragment_lines: LineMap::default(),
+            geometry_lines: LineMap::default(),
+            tess_control_lines: LineMap::default(),
+            tess_evaluation_lines: LineMap::default(),
         };
 
         let mut pragma = ShaderPragma::Shared;
+        let mut keywords: Vec<String> = vec![];
 
-        for line in reader.lines() {
-            let mut line = line? + "\n";
+        for source_line in source.lines {
+            let (file, line_number) = (source_line.file, source_line.line);
+            let mut line = source_line.text + "\n";
 
             if line.trim().len() == 0 {
                 continue;
@@ -171,11 +372,23 @@ impl ShaderSource {
             if line.starts_with("#pragma") {
                 let pragma_str = line[8..].trim();
 
+                if let Some(names) = pragma_str.strip_prefix("keywords ") {
+                    for name in names.split_whitespace() {
+                        if !keywords.iter().any(|keyword| keyword == name) {
+                            keywords.push(name.to_string());
+                        }
+                    }
+
+                    continue;
+                }
+
                 pragma = match pragma_str {
                     "shared" => ShaderPragma::Shared,
                     "vertex" => ShaderPragma::Vertex,
                     "fragment" => ShaderPragma::Fragment,
                     "geometry" => ShaderPragma::Geometry,
+                    "tess_control" => ShaderPragma::TessControl,
+                    "tess_evaluation" => ShaderPragma::TessEvaluation,
                     _ => return Err(ShaderError::UnknownPragma(String::from(pragma_str))),
                 };
 
@@ -212,6 +425,28 @@ impl ShaderSource {
                         is_geometry_entry_point = true;
                         line = replace_entry_with_main(&mut line)?;
                     }
+                    ShaderPragma::TessControl => {
+                        if is_tess_control_entry_point {
+                            return Err(ShaderError::EntryPointError(
+                                "Tessellation control shader entry point already defined"
+                                    .to_string(),
+                            ));
+                        }
+
+                        is_tess_control_entry_point = true;
+                        line = replace_entry_with_main(&line)?;
+                    }
+                    ShaderPragma::TessEvaluation => {
+                        if is_tess_evaluation_entry_point {
+                            return Err(ShaderError::EntryPointError(
+                                "Tessellation evaluation shader entry point already defined"
+                                    .to_string(),
+                            ));
+                        }
+
+                        is_tess_evaluation_entry_point = true;
+                        line = replace_entry_with_main(&line)?;
+                    }
                     ShaderPragma::Shared => {
                         return Err(ShaderError::EntryPointError(
                             "No entry point allowed for shared GLSL code".to_string(),
@@ -225,15 +460,33 @@ impl ShaderSource {
                     shader_source.vertex_source += line.as_str();
                     shader_source.fragment_source += line.as_str();
                     shader_source.geometry_source += line.as_str();
+                    shader_source.tess_control_source += line.as_str();
+                    shader_source.tess_evaluation_source += line.as_str();
+                    shader_source.vertex_lines.push(file, line_number);
+                    shader_source.fragment_lines.push(file, line_number);
+                    shader_source.geometry_lines.push(file, line_number);
+                    shader_source.tess_control_lines.push(file, line_number);
+                    shader_source.tess_evaluation_lines.push(file, line_number);
                 }
                 ShaderPragma::Vertex => {
                     shader_source.vertex_source += line.as_str();
+                    shader_source.vertex_lines.push(file, line_number);
                 }
                 ShaderPragma::Fragment => {
                     shader_source.fragment_source += line.as_str();
+                    shader_source.fragment_lines.push(file, line_number);
                 }
                 ShaderPragma::Geometry => {
                     shader_source.geometry_source += line.as_str();
+                    shader_source.geometry_lines.push(file, line_number);
+                }
+                ShaderPragma::TessControl => {
+                    shader_source.tess_control_source += line.as_str();
+                    shader_source.tess_control_lines.push(file, line_number);
+                }
+                ShaderPragma::TessEvaluation => {
+                    shader_source.tess_evaluation_source += line.as_str();
+                    shader_source.tess_evaluation_lines.push(file, line_number);
                 }
             }
         }
@@ -244,6 +497,9 @@ impl ShaderSource {
         if !is_fragment_entry_point {
             return Err(ShaderError::MissingFragmentEntryPoint);
         }
+        if is_tess_control_entry_point && !is_tess_evaluation_entry_point {
+            return Err(ShaderError::MissingTessEvaluationEntryPoint);
+        }
 
         Ok(ShaderSource {
             vertex_source: shader_source.vertex_source,
@@ -253,54 +509,191 @@ impl ShaderSource {
             } else {
                 None
             },
+            tess_control_source: if is_tess_control_entry_point {
+                Some(shader_source.tess_control_source)
+            } else {
+                None
+            },
+            tess_evaluation_source: if is_tess_evaluation_entry_point {
+                Some(shader_source.tess_evaluation_source)
+            } else {
+                None
+            },
+            files: source.files,
+            vertex_lines: shader_source.vertex_lines,
+            fragment_lines: shader_source.fragment_lines,
+            geometry_lines: shader_source.geometry_lines,
+            tess_control_lines: shader_source.tess_control_lines,
+            tess_evaluation_lines: shader_source.tess_evaluation_lines,
+            keywords,
         })
     }
+
+    /// Copy of the source with a `#define` for each keyword, right after the
+    /// `#version` directive of every stage
+    pub fn with_defines(&self, keywords: &[String]) -> ShaderSource {
+        let define = |source: &str, lines: &LineMap| {
+            let index = source
+                .split_inclusive('\n')
+                .position(|line| line.trim_start().starts_with("#version"))
+                .map_or(0, |index| index + 1);
+
+            let mut stage: String = source.split_inclusive('\n').take(index).collect();
+
+            for keyword in keywords {
+                stage += &format!("#define {keyword}\n");
+            }
+
+            stage.extend(source.split_inclusive('\n').skip(index));
+
+            let mut lines = lines.clone();
+            lines.insert(index, keywords.len());
+
+            (stage, lines)
+        };
+
+        let (vertex_source, vertex_lines) = define(&self.vertex_source, &self.vertex_lines);
+        let (fragment_source, fragment_lines) = define(&self.fragment_source, &self.fragment_lines);
+        let define_optional = |source: &Option<String>, lines: &LineMap| match source {
+            Some(source) => {
+                let (source, lines) = define(source, lines);
+                (Some(source), lines)
+            }
+            None => (None, lines.clone()),
+        };
+
+        let (geometry_source, geometry_lines) =
+            define_optional(&self.geometry_source, &self.geometry_lines);
+        let (tess_control_source, tess_control_lines) =
+            define_optional(&self.tess_control_source, &self.tess_control_lines);
+        let (tess_evaluation_source, tess_evaluation_lines) =
+            define_optional(&self.tess_evaluation_source, &self.tess_evaluation_lines);
+
+        ShaderSource {
+            vertex_source,
+            fragment_source,
+            geometry_source,
+            tess_control_source,
+            tess_evaluation_source,
+            files: self.files.clone(),
+            vertex_lines,
+            fragment_lines,
+            geometry_lines,
+            tess_control_lines,
+            tess_evaluation_lines,
+            keywords: self.keywords.clone(),
+        }
+    }
+
+    /// Points the locations of a compile log at the original files
+    fn locate_error(&self, error: ShaderError) -> ShaderError {
+        match error {
+            ShaderError::VertexCompileError(log) => {
+                ShaderError::VertexCompileError(self.vertex_lines.rewrite_log(&log, &self.files))
+            }
+            ShaderError::FragmentCompileError(log) => ShaderError::FragmentCompileError(
+                self.fragment_lines.rewrite_log(&log, &self.files),
+            ),
+            ShaderError::GeometryCompileError(log) => ShaderError::GeometryCompileError(
+                self.geometry_lines.rewrite_log(&log, &self.files),
+            ),
+            ShaderError::TessControlCompileError(log) => ShaderError::TessControlCompileError(
+                self.tess_control_lines.rewrite_log(&log, &self.files),
+            ),
+            ShaderError::TessEvaluationCompileError(log) => {
+                ShaderError::TessEvaluationCompileError(
+                    self.tess_evaluation_lines.rewrite_log(&log, &self.files),
+                )
+            }
+            other => other,
+        }
+    }
 }
 
 impl Shader {
     pub fn from_file<P: Into<PathBuf>>(path: P) -> Result<Self, ShaderError> {
-        let source = ShaderSource::from_file(path)?;
-        Self::from_source(&source)
-    }
+        let path = path.into();
+        let source = ShaderSource::from_file(path.clone())?;
 
-    pub fn bind(&self) {
-        unsafe { gl::UseProgram(self.program.raw) }
-    }
+        let mut shader = Self::from_source(&source)?;
 
-    pub fn unbind() {
-        unsafe { gl::UseProgram(0) }
+        shader.origin = Some(ShaderOrigin {
+            path,
+            files: source
+                .files
+                .into_iter()
+                .map(|file| {
+                    let modified = modified_time(&file);
+                    (file, modified)
+                })
+                .collect(),
+        });
+
+        Ok(shader)
     }
 
-    pub unsafe fn raw_program(&self) -> u32 {
-        self.program.raw
+    /// File the shader was loaded from, if it was
+    pub fn path(&self) -> Option<&Path> {
+        Some(&self.origin.as_ref()?.path)
     }
 
-    pub fn set_uniform_1f(&self, location: NonNegativeI32, value: f32) {
-        self.bind();
-        unsafe {
-            gl::Uniform1f(location.0, value);
+    /// Whether a file the shader was built from changed since then
+    pub fn is_outdated(&self) -> bool {
+        match self.origin {
+            Some(ref origin) => origin
+                .files
+                .iter()
+                .any(|(file, modified)| modified_time(file) != *modified),
+            None => false,
         }
-        Self::unbind();
     }
 
-    pub fn set_uniform_1f_opt(&self, location: Option<NonNegativeI32>, value: f32) {
-        if let Some(location) = location {
-            self.set_uniform_1f(location, value);
+    /// Rebuilds the shader if its files changed, returning whether it did.
+    /// When the new version fails to build, the current program is kept and
+    /// the error is only reported once per change
+    pub fn reload(&mut self) -> Result<bool, ShaderError> {
+        if !self.is_outdated() {
+            return Ok(false);
         }
-    }
 
-    pub fn set_uniform_1i(&self, location: NonNegativeI32, value: i32) {
-        self.bind();
-        unsafe {
-            gl::Uniform1i(location.0, value);
+        let origin = match self.origin {
+            Some(ref mut origin) => origin,
+            None => return Ok(false),
+        };
+
+        match Self::from_file(origin.path.clone()) {
+            Ok(shader) => {
+                *self = shader;
+                Ok(true)
+            }
+            Err(error) => {
+                // Files the failed version added are watched too, so that
+                // fixing them triggers the next reload
+                for file in ExpandedSource::dependencies(&origin.path) {
+                    if !origin.files.iter().any(|(watched, _)| *watched == file) {
+                        origin.files.push((file, None));
+                    }
+                }
+
+                for (file, modified) in &mut origin.files {
+                    *modified = modified_time(file);
+                }
+
+                Err(error)
+            }
         }
-        Self::unbind();
     }
 
-    pub fn set_uniform_1i_opt(&self, location: Option<NonNegativeI32>, value: i32) {
-        if let Some(location) = location {
-            self.set_uniform_1i(location, value);
-        }
+    pub fn bind(&self) {
+        gl_state::use_program(self.program.raw);
+    }
+
+    pub fn unbind() {
+        gl_state::use_program(0);
+    }
+
+    pub unsafe fn raw_program(&self) -> u32 {
+        self.program.raw
     }
 
     unsafe fn get_uniform_info(program: u32) -> HashMap<String, ShaderUniformInfo> {
@@ -321,6 +714,21 @@ impl Shader {
         let mut size: GLint = 0;
 
         for i in 0..uniform_count {
+            let mut block_index: GLint = -1;
+
+            gl::GetActiveUniformsiv(
+                program,
+                1,
+                &(i as GLuint),
+                gl::UNIFORM_BLOCK_INDEX,
+                &mut block_index,
+            );
+
+            // Members of uniform blocks are set through buffers
+            if block_index != -1 {
+                continue;
+            }
+
             gl::GetActiveUniform(
                 program,
                 i as GLuint,
@@ -338,6 +746,12 @@ impl Shader {
 
             let name = String::from_utf8(buffer.clone()).unwrap();
 
+            // Arrays are reported as their first element
+            let name = match name.strip_suffix("[0]") {
+                Some(array_name) => array_name.to_string(),
+                None => name,
+            };
+
             let mut buffer = name.as_bytes().to_vec();
             buffer.push(0);
 
@@ -355,6 +769,94 @@ impl Shader {
                     name,
                     location,
                     typ: ShaderUniformType::from_raw(typ),
+                    size: size.max(1) as usize,
+                    unit: None,
+                },
+            );
+        }
+
+        ret
+    }
+
+    unsafe fn get_attribute_info(program: u32) -> Vec<ShaderAttributeInfo> {
+        let mut attribute_count: GLint = 0;
+
+        gl::GetProgramiv(program, gl::ACTIVE_ATTRIBUTES, &mut attribute_count);
+
+        let mut ret = vec![];
+
+        let mut buffer: [u8; 512] = [0; 512];
+
+        let mut length: GLsizei = 0;
+        let mut typ: GLenum = 0;
+        let mut size: GLint = 0;
+
+        for i in 0..attribute_count.max(0) as GLuint {
+            gl::GetActiveAttrib(
+                program,
+                i,
+                511,
+                &mut length,
+                &mut size,
+                &mut typ,
+                buffer.as_mut_ptr() as _,
+            );
+
+            let name = String::from_utf8_lossy(&buffer[..(length as usize)]).into_owned();
+
+            let name = CString::new(name).unwrap();
+            let location = gl::GetAttribLocation(program, name.as_ptr());
+
+            // Built-in inputs like `gl_VertexID` have no location
+            if location < 0 {
+                continue;
+            }
+
+            ret.push(ShaderAttributeInfo {
+                name: name.into_string().unwrap(),
+                location: location as u32,
+                typ: ShaderUniformType::from_raw(typ),
+            });
+        }
+
+        ret.sort_by_key(|attribute| attribute.location);
+
+        ret
+    }
+
+    unsafe fn get_uniform_block_info(program: u32) -> HashMap<String, ShaderUniformBlockInfo> {
+        let mut block_count: GLint = 0;
+
+        gl::GetProgramiv(program, gl::ACTIVE_UNIFORM_BLOCKS, &mut block_count);
+
+        let mut ret = HashMap::new();
+
+        let mut buffer: [u8; 512] = [0; 512];
+        let mut length: GLsizei = 0;
+
+        for index in 0..block_count.max(0) as u32 {
+            gl::GetActiveUniformBlockName(
+                program,
+                index,
+                511,
+                &mut length,
+                buffer.as_mut_ptr() as _,
+            );
+
+            let name = String::from_utf8_lossy(&buffer[..(length as usize)]).into_owned();
+
+            let mut size: GLint = 0;
+
+            gl::GetActiveUniformBlockiv(program, index, gl::UNIFORM_BLOCK_DATA_SIZE, &mut size);
+
+            let binding = UniformBlockBinding::from_block_name(&name);
+
+            ret.insert(
+                name,
+                ShaderUniformBlockInfo {
+                    index,
+                    size: size as usize,
+                    binding,
                 },
             );
         }
@@ -363,35 +865,154 @@ impl Shader {
     }
 
     pub fn from_source(source: &ShaderSource) -> Result<Self, ShaderError> {
+        let mut shader = Self::compile(source)?;
+
+        if !source.keywords.is_empty() {
+            shader.source = Some(source.clone());
+        }
+
+        Ok(shader)
+    }
+
+    /// Restores the program from the binary cache, or builds and caches it
+    fn compile(source: &ShaderSource) -> Result<Self, ShaderError> {
+        let key = ProgramKey::new(source);
+
+        let program = match unsafe { binary_cache::load(&key) } {
+            Some(program) => program,
+            None => {
+                let program = Self::compile_program(source)?;
+                unsafe { binary_cache::store(&key, &program) };
+                program
+            }
+        };
+
+        let raw = program.raw;
+
+        let mut shader = Self {
+            program,
+            uniforms: unsafe { Self::get_uniform_info(raw) },
+            uniform_blocks: unsafe { Self::get_uniform_block_info(raw) },
+            attributes: unsafe { Self::get_attribute_info(raw) },
+            checked_meshes: RefCell::new(HashSet::new()),
+            origin: None,
+            source: None,
+            variants: HashMap::new(),
+        };
+
+        shader.assign_sampler_units()?;
+        shader.bind_uniform_blocks()?;
+
+        Ok(shader)
+    }
+
+    fn compile_program(source: &ShaderSource) -> Result<RawProgram, ShaderError> {
         let vertex_shader;
         let fragment_shader;
         let geometry_shader;
+        let tess_control_shader;
+        let tess_evaluation_shader;
 
         let program;
 
+        let compile = |stage_source: &str, typ| unsafe {
+            Self::compile_shader(stage_source, typ).map_err(|error| source.locate_error(error))
+        };
+
         unsafe {
-            vertex_shader = Self::compile_shader(&source.vertex_source, gl::VERTEX_SHADER)?;
+            vertex_shader = compile(&source.vertex_source, gl::VERTEX_SHADER)?;
             geometry_shader = match source.geometry_source {
-                Some(ref source) => Some(Self::compile_shader(source, gl::GEOMETRY_SHADER)?),
+                Some(ref geometry_source) => Some(compile(geometry_source, gl::GEOMETRY_SHADER)?),
+                None => None,
+            };
+            tess_control_shader = match source.tess_control_source {
+                Some(ref tess_control_source) => {
+                    Some(compile(tess_control_source, gl::TESS_CONTROL_SHADER)?)
+                }
                 None => None,
             };
-            fragment_shader = Self::compile_shader(&source.fragment_source, gl::FRAGMENT_SHADER)?;
+            tess_evaluation_shader = match source.tess_evaluation_source {
+                Some(ref tess_evaluation_source) => {
+                    Some(compile(tess_evaluation_source, gl::TESS_EVALUATION_SHADER)?)
+                }
+                None => None,
+            };
+            fragment_shader = compile(&source.fragment_source, gl::FRAGMENT_SHADER)?;
 
             let mut shaders = vec![&vertex_shader, &fragment_shader];
 
-            if let Some(ref geometry_shader) = geometry_shader {
-                shaders.push(geometry_shader);
-            }
+            let optional_stages = [
+                &geometry_shader,
+                &tess_control_shader,
+                &tess_evaluation_shader,
+            ];
+
+            shaders.extend(optional_stages.into_iter().flatten());
 
             program = Self::link_shaders(&shaders[..])?;
         }
 
-        let raw = program.raw;
+        Ok(program)
+    }
 
-        Ok(Self {
-            program,
-            uniforms: unsafe { Self::get_uniform_info(raw) },
-        })
+    /// Gives every sampler its own texture unit, in name order. Elements of
+    /// sampler arrays get consecutive units
+    fn assign_sampler_units(&mut self) -> Result<(), ShaderError> {
+        let program = self.program.raw;
+
+        let mut samplers: Vec<_> = self
+            .uniforms
+            .values_mut()
+            .filter(|uniform| uniform.typ.is_sampler())
+            .collect();
+
+        samplers.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let count = samplers.iter().map(|sampler| sampler.size).sum();
+
+        let mut allocator = TextureUnitAllocator::new();
+        let units: Option<Vec<Vec<_>>> = samplers
+            .iter()
+            .map(|sampler| (0..sampler.size).map(|_| allocator.allocate()).collect())
+            .collect();
+        let units = units.ok_or(ShaderError::TooManySamplers(count))?;
+
+        for (sampler, units) in samplers.into_iter().zip(units) {
+            let indices: Vec<i32> = units.iter().map(|unit| unit.index() as i32).collect();
+
+            if let Some(location) = sampler.location {
+                unsafe {
+                    gl::ProgramUniform1iv(program, location.0, indices.len() as _, indices.as_ptr())
+                };
+            }
+
+            sampler.unit = units.first().copied();
+        }
+
+        Ok(())
+    }
+
+    /// Points the shared blocks at their fixed binding, once their layout is
+    /// known to match. GLSL 4.10 can't set the binding in the source
+    fn bind_uniform_blocks(&self) -> Result<(), ShaderError> {
+        for (name, block) in &self.uniform_blocks {
+            let binding = match block.binding {
+                Some(binding) => binding,
+                None => continue,
+            };
+
+            if block.size != binding.block_size() {
+                return Err(ShaderError::UniformBlockMismatch {
+                    name: name.clone(),
+                    expected: binding.block_size(),
+                    got: block.size,
+                });
+            }
+
+            unsafe { gl::UniformBlockBinding(self.program.raw, block.index, binding.index()) };
+        }
+
+        Ok(())
     }
 
     unsafe fn compile_shader(source: &str, typ: GLenum) -> Result<RawShader, ShaderError> {
@@ -427,6 +1048,8 @@ impl Shader {
                 gl::VERTEX_SHADER => Err(ShaderError::VertexCompileError(log)),
                 gl::FRAGMENT_SHADER => Err(ShaderError::FragmentCompileError(log)),
                 gl::GEOMETRY_SHADER => Err(ShaderError::GeometryCompileError(log)),
+                gl::TESS_CONTROL_SHADER => Err(ShaderError::TessControlCompileError(log)),
+                gl::TESS_EVALUATION_SHADER => Err(ShaderError::TessEvaluationCompileError(log)),
                 _ => unreachable!("Invalid shader type: {:?}", typ),
             };
         }
@@ -441,6 +1064,11 @@ impl Shader {
             gl::AttachShader(program, shader.raw);
         }
 
+        gl::ProgramParameteri(
+            program,
+            gl::PROGRAM_BINARY_RETRIEVABLE_HINT,
+            gl::TRUE as GLint,
+        );
         gl::LinkProgram(program);
 
         let mut success = gl::FALSE as GLint;
@@ -473,4 +1101,149 @@ impl Shader {
     pub fn uniform_location(&self, name: &str) -> Option<NonNegativeI32> {
         Some(self.uniforms.get(name)?.location?)
     }
+
+    /// Sets a uniform by name, checking the value against its declared type.
+    /// Names the shader doesn't use are ignored, like GL does for location -1
+    pub fn set_uniform<V: UniformValue + ?Sized>(
+        &self,
+        name: &str,
+        value: &V,
+    ) -> Result<(), UniformError> {
+        let uniform = match self.uniforms.get(name) {
+            Some(uniform) => uniform,
+            None => return Ok(()),
+        };
+
+        if uniform.typ != value.uniform_type() {
+            return Err(UniformError::TypeMismatch {
+                name: name.to_string(),
+                expected: uniform.typ,
+                got: value.uniform_type(),
+            });
+        }
+
+        if value.len() > uniform.size {
+            return Err(UniformError::TooManyElements {
+                name: name.to_string(),
+                size: uniform.size,
+                got: value.len(),
+            });
+        }
+
+        if let Some(location) = uniform.location {
+            unsafe { value.upload(self.program.raw, location.0) };
+        }
+
+        Ok(())
+    }
+
+    /// Keywords declared with `#pragma keywords`
+    pub fn keywords(&self) -> &[String] {
+        match self.source {
+            Some(ref source) => &source.keywords,
+            None => &[],
+        }
+    }
+
+    /// The program compiled with a `#define` for each of `keywords`, the
+    /// first time it's asked for. Without keywords that's this shader, which
+    /// a variant that failed to compile also falls back to after its error
+    /// was returned once
+    pub fn variant(&mut self, keywords: &[&str]) -> Result<&Shader, ShaderError> {
+        if let Some(keyword) = keywords
+            .iter()
+            .find(|&&keyword| !self.keywords().iter().any(|declared| declared == keyword))
+        {
+            return Err(ShaderError::UnknownKeyword(keyword.to_string()));
+        }
+
+        let source = match self.source {
+            Some(ref source) if !keywords.is_empty() => source,
+            _ => return Ok(self),
+        };
+
+        let key: Vec<String> = source
+            .keywords
+            .iter()
+            .filter(|declared| keywords.contains(&declared.as_str()))
+            .cloned()
+            .collect();
+
+        if !self.variants.contains_key(&key) {
+            let variant = Self::compile(&source.with_defines(&key));
+
+            match variant {
+                Ok(variant) => self.variants.insert(key.clone(), Some(variant)),
+                Err(error) => {
+                    self.variants.insert(key, None);
+                    return Err(error);
+                }
+            };
+        }
+
+        match self.variants.get(&key) {
+            Some(Some(variant)) => Ok(variant),
+            _ => Ok(self),
+        }
+    }
+
+    pub fn uniform_block(&self, name: &str) -> Option<&ShaderUniformBlockInfo> {
+        self.uniform_blocks.get(name)
+    }
+
+    /// Texture unit of an active sampler uniform
+    pub fn sampler_unit(&self, name: &str) -> Option<TextureUnit> {
+        self.uniforms.get(name)?.unit
+    }
+
+    pub fn attributes(&self) -> &[ShaderAttributeInfo] {
+        &self.attributes
+    }
+
+    /// Compares the attributes the vertex shader reads with those provided,
+    /// by location. Channels a mesh doesn't have read their default value,
+    /// so only unknown locations are missing
+    pub fn validate_attributes(&self, provided: &[VertexAttribute]) -> Vec<AttributeMismatch> {
+        self.attributes
+            .iter()
+            .filter_map(|attribute| {
+                let provided = provided
+                    .iter()
+                    .find(|provided| provided.location() == attribute.location);
+
+                match provided {
+                    None if VertexChannel::from_location(attribute.location).is_some() => None,
+                    None => Some(AttributeMismatch::Missing {
+                        name: attribute.name.clone(),
+                        location: attribute.location,
+                    }),
+                    Some(provided) if provided.shader_type() != attribute.typ => {
+                        Some(AttributeMismatch::TypeMismatch {
+                            name: attribute.name.clone(),
+                            location: attribute.location,
+                            expected: attribute.typ,
+                            got: provided.shader_type(),
+                        })
+                    }
+                    Some(_) => None,
+                }
+            })
+            .collect()
+    }
+
+    /// Prints what `validate_attributes` finds for a mesh, the first time the
+    /// mesh is drawn with this shader
+    pub fn warn_attribute_mismatches(&self, mesh: &Mesh) {
+        if !self.checked_meshes.borrow_mut().insert(mesh.id()) {
+            return;
+        }
+
+        for mismatch in self.validate_attributes(mesh.attributes()) {
+            let path = self
+                .path()
+                .map_or("<shader>".into(), |path| path.display().to_string());
+
+            eprintln!("{path}: vertex attribute mismatch: {mismatch:?}");
+        }
+    }
 }
@@ -0,0 +1,200 @@
+use std::{fs, path::PathBuf, sync::OnceLock};
+
+use gl::types::{GLenum, GLint};
+
+use crate::opengl::driver::DriverInfo;
+
+use super::{RawProgram, ShaderSource};
+
+/// Identifies a linked program: its preprocessed stages and the driver that
+/// built it, since binaries are only valid for the exact same driver
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProgramKey {
+    /// Names the cache file
+    hash: u64,
+    /// Everything the hash was computed from, stored in the file and compared
+    /// on load, so that programs with the same hash can't be mixed up
+    bytes: Vec<u8>,
+}
+
+/// FNV-1a, stable across builds unlike `DefaultHasher`
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Length-prefixed, so that consecutive strings can't run into each other
+fn write_str(bytes: &mut Vec<u8>, text: &str) {
+    bytes.extend_from_slice(&(text.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(text.as_bytes());
+}
+
+fn driver_info() -> &'static DriverInfo {
+    static DRIVER_INFO: OnceLock<DriverInfo> = OnceLock::new();
+
+    DRIVER_INFO.get_or_init(DriverInfo::query)
+}
+
+/// Drivers may support no binary format at all, in which case nothing is
+/// cached
+fn is_supported() -> bool {
+    static SUPPORTED: OnceLock<bool> = OnceLock::new();
+
+    *SUPPORTED.get_or_init(|| {
+        let mut formats: GLint = 0;
+        unsafe { gl::GetIntegerv(gl::NUM_PROGRAM_BINARY_FORMATS, &mut formats) };
+        formats > 0
+    })
+}
+
+/// `$SCOP_SHADER_CACHE`, or `scop/shaders` in the user cache directory
+fn cache_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("SCOP_SHADER_CACHE") {
+        return Some(PathBuf::from(dir));
+    }
+
+    let cache_home = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+
+    Some(cache_home.join("scop").join("shaders"))
+}
+
+impl ProgramKey {
+    pub fn new(source: &ShaderSource) -> Self {
+        let mut bytes = vec![];
+
+        let driver = driver_info();
+
+        for text in [&driver.vendor, &driver.renderer, &driver.version] {
+            write_str(&mut bytes, text);
+        }
+
+        let stages = [
+            Some(&source.vertex_source),
+            Some(&source.fragment_source),
+            source.geometry_source.as_ref(),
+            source.tess_control_source.as_ref(),
+            source.tess_evaluation_source.as_ref(),
+        ];
+
+        for stage in stages {
+            match stage {
+                Some(text) => write_str(&mut bytes, text),
+                None => bytes.push(0xff),
+            }
+        }
+
+        Self {
+            hash: fnv1a(&bytes),
+            bytes,
+        }
+    }
+
+    fn path(&self) -> Option<PathBuf> {
+        Some(cache_dir()?.join(format!("{:016x}.bin", self.hash)))
+    }
+}
+
+/// Binary format and key length in front of the key, then the binary
+const HEADER_SIZE: usize = 4 + 8;
+
+/// Restores a program stored by `store`. A missing, stale or rejected binary
+/// gives `None`, and the file is removed so it gets stored again
+pub unsafe fn load(key: &ProgramKey) -> Option<RawProgram> {
+    if !is_supported() {
+        return None;
+    }
+
+    let path = key.path()?;
+    let bytes = fs::read(&path).ok()?;
+
+    if bytes.len() < HEADER_SIZE {
+        let _ = fs::remove_file(&path);
+        return None;
+    }
+
+    let (header, rest) = bytes.split_at(HEADER_SIZE);
+    let format = GLenum::from_le_bytes(header[..4].try_into().unwrap());
+    let length = u64::from_le_bytes(header[4..].try_into().unwrap());
+
+    // Another program with the same hash, or a truncated file
+    let binary = match rest.strip_prefix(key.bytes.as_slice()) {
+        Some(binary) if length == key.bytes.len() as u64 => binary,
+        _ => {
+            let _ = fs::remove_file(&path);
+            return None;
+        }
+    };
+
+    let program = RawProgram {
+        raw: gl::CreateProgram(),
+    };
+
+    gl::ProgramBinary(program.raw, format, binary.as_ptr() as _, binary.len() as _);
+
+    let mut success = gl::FALSE as GLint;
+    gl::GetProgramiv(program.raw, gl::LINK_STATUS, &mut success);
+
+    if success != gl::TRUE as GLint {
+        let _ = fs::remove_file(&path);
+        return None;
+    }
+
+    Some(program)
+}
+
+/// Writes a linked program to the cache. Failures only cost a compile on the
+/// next start, so they are reported and otherwise ignored. The file is
+/// written under a temporary name first, so that an interrupted write or
+/// another instance never leaves a partial binary behind
+pub unsafe fn store(key: &ProgramKey, program: &RawProgram) {
+    if !is_supported() {
+        return;
+    }
+
+    let path = match key.path() {
+        Some(path) => path,
+        None => return,
+    };
+
+    let mut length: GLint = 0;
+    gl::GetProgramiv(program.raw, gl::PROGRAM_BINARY_LENGTH, &mut length);
+
+    if length <= 0 {
+        return;
+    }
+
+    let mut binary = vec![0u8; length as usize];
+    let mut format: GLenum = 0;
+    let mut written: GLint = 0;
+
+    gl::GetProgramBinary(
+        program.raw,
+        length,
+        &mut written,
+        &mut format,
+        binary.as_mut_ptr() as _,
+    );
+
+    binary.truncate(written.max(0) as usize);
+
+    let mut bytes = format.to_le_bytes().to_vec();
+    bytes.extend_from_slice(&(key.bytes.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(&key.bytes);
+    bytes.extend_from_slice(&binary);
+
+    let temp_path = path.with_extension(format!("{}.tmp", std::process::id()));
+
+    let result = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(&temp_path, bytes))
+        .and_then(|_| fs::rename(&temp_path, &path));
+
+    if let Err(e) = result {
+        let _ = fs::remove_file(&temp_path);
+        eprintln!("Could not cache shader program {}: {e}", path.display());
+    }
+}
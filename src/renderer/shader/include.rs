@@ -0,0 +1,318 @@
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::{Path, PathBuf},
+};
+
+use super::ShaderError;
+
+#[derive(Debug)]
+pub enum IncludeErrorDetail {
+    /// Anything but `#include "path"`
+    MalformedDirective(String),
+    IoError(PathBuf, io::Error),
+    /// The file is already being included further up the chain
+    Cycle(PathBuf),
+}
+
+/// A line of the expanded source, with where it came from
+#[derive(Debug)]
+pub struct SourceLine {
+    /// Index into `ExpandedSource::files`
+    pub file: usize,
+    /// 1-based line number in that file
+    pub line: usize,
+    pub text: String,
+}
+
+#[derive(Debug, Default)]
+pub struct ExpandedSource {
+    pub lines: Vec<SourceLine>,
+    /// Every file read, the including one first
+    pub files: Vec<PathBuf>,
+}
+
+fn parse_include(text: &str) -> Option<Option<&str>> {
+    let rest = text.trim().strip_prefix("#include")?;
+
+    let path = rest
+        .trim()
+        .strip_prefix('"')
+        .and_then(|rest| rest.strip_suffix('"'))
+        .filter(|path| !path.is_empty());
+
+    Some(path)
+}
+
+/// Tells whether a `#pragma` starts a stage section, and if it is the shared
+/// one. Other pragmas, such as `keywords`, don't
+fn parse_stage_pragma(text: &str) -> Option<bool> {
+    let pragma = text.trim().strip_prefix("#pragma")?.trim();
+
+    match pragma.split_whitespace().next()? {
+        "keywords" => None,
+        stage => Some(stage == "shared"),
+    }
+}
+
+/// Include-once is scoped to each stage section, since sections end up in
+/// different programs. Files included in the shared section, which every
+/// stage gets, aren't expanded again
+struct IncludeState {
+    stack: Vec<PathBuf>,
+    /// Files expanded in the current section
+    included: HashSet<PathBuf>,
+    /// Files expanded in shared sections
+    shared: HashSet<PathBuf>,
+    in_shared: bool,
+}
+
+impl IncludeState {
+    fn new() -> Self {
+        Self {
+            stack: vec![],
+            included: HashSet::new(),
+            shared: HashSet::new(),
+            in_shared: true,
+        }
+    }
+
+    fn insert(&mut self, path: &Path) {
+        self.included.insert(path.to_path_buf());
+
+        if self.in_shared {
+            self.shared.insert(path.to_path_buf());
+        }
+    }
+
+    fn start_section(&mut self, shared: bool) {
+        self.in_shared = shared;
+        self.included = self.shared.clone();
+    }
+}
+
+impl ExpandedSource {
+    /// Reads a file with its `#include "path"` directives replaced by the
+    /// included files. Paths are relative to the including file, and a file
+    /// included twice in a stage section is only expanded the first time
+    pub fn from_file(path: &Path) -> Result<Self, ShaderError> {
+        let mut source = Self::default();
+
+        let path = path.canonicalize()?;
+
+        source.expand(path, &mut IncludeState::new())?;
+
+        Ok(source)
+    }
+
+    /// Files read while expanding `path`, up to the first error if there is
+    /// one. A missing include is listed too, so that creating it is noticed
+    pub fn dependencies(path: &Path) -> Vec<PathBuf> {
+        let mut source = Self::default();
+
+        let result = path
+            .canonicalize()
+            .map_err(ShaderError::from)
+            .and_then(|path| source.expand(path, &mut IncludeState::new()));
+
+        if let Err(ShaderError::IncludeError {
+            detail: IncludeErrorDetail::IoError(missing, _),
+            ..
+        }) = result
+        {
+            source.files.push(missing);
+        }
+
+        source.files
+    }
+
+    fn expand(&mut self, path: PathBuf, state: &mut IncludeState) -> Result<(), ShaderError> {
+        let reader = BufReader::new(File::open(&path)?);
+
+        let file = self.files.len();
+        self.files.push(path.clone());
+        state.insert(&path);
+        state.stack.push(path.clone());
+
+        for (i, text) in reader.lines().enumerate() {
+            let text = text?;
+            let line = i + 1;
+
+            let error = |detail| ShaderError::IncludeError {
+                file: path.clone(),
+                line,
+                detail,
+            };
+
+            let include = match parse_include(&text) {
+                None => {
+                    if let Some(shared) = parse_stage_pragma(&text) {
+                        state.start_section(shared);
+                    }
+
+                    self.lines.push(SourceLine { file, line, text });
+                    continue;
+                }
+                Some(None) => return Err(error(IncludeErrorDetail::MalformedDirective(text))),
+                Some(Some(include)) => include,
+            };
+
+            let include = path.parent().unwrap_or(Path::new("")).join(include);
+            let include = include
+                .canonicalize()
+                .map_err(|e| error(IncludeErrorDetail::IoError(include, e)))?;
+
+            if state.stack.contains(&include) {
+                return Err(error(IncludeErrorDetail::Cycle(include)));
+            }
+
+            if state.included.contains(&include) {
+                continue;
+            }
+
+            self.expand(include, state)?;
+        }
+
+        state.stack.pop();
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    /// Writes `files` to a fresh directory, returning its path
+    fn write_files(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("scop-include-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        for (path, text) in files {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, text).unwrap();
+        }
+
+        dir
+    }
+
+    fn texts(source: &ExpandedSource) -> Vec<&str> {
+        source.lines.iter().map(|line| line.text.as_str()).collect()
+    }
+
+    #[test]
+    fn resolves_paths_relative_to_the_including_file() {
+        let dir = write_files(
+            "relative",
+            &[
+                ("main.glsl", "#include \"include/a.glsl\"\nmain"),
+                ("include/a.glsl", "#include \"../b.glsl\"\na"),
+                ("b.glsl", "b"),
+            ],
+        );
+
+        let source = ExpandedSource::from_file(&dir.join("main.glsl")).unwrap();
+
+        assert_eq!(texts(&source), ["b", "a", "main"]);
+        assert_eq!(source.lines[0].file, 2);
+        assert_eq!(source.lines[2].line, 2);
+    }
+
+    #[test]
+    fn includes_once_per_stage_section() {
+        let dir = write_files(
+            "once",
+            &[
+                (
+                    "main.glsl",
+                    "#include \"shared.glsl\"\n\
+                     #pragma vertex\n\
+                     #include \"a.glsl\"\n\
+                     #include \"a.glsl\"\n\
+                     #include \"shared.glsl\"\n\
+                     #pragma keywords A\n\
+                     #include \"a.glsl\"\n\
+                     #pragma fragment\n\
+                     #include \"a.glsl\"",
+                ),
+                ("a.glsl", "a"),
+                ("shared.glsl", "shared"),
+            ],
+        );
+
+        let source = ExpandedSource::from_file(&dir.join("main.glsl")).unwrap();
+
+        assert_eq!(
+            texts(&source),
+            [
+                "shared",
+                "#pragma vertex",
+                "a",
+                "#pragma keywords A",
+                "#pragma fragment",
+                "a"
+            ]
+        );
+    }
+
+    #[test]
+    fn detects_cycles() {
+        let dir = write_files(
+            "cycle",
+            &[
+                ("main.glsl", "#include \"a.glsl\""),
+                ("a.glsl", "#include \"b.glsl\""),
+                ("b.glsl", "\n#include \"a.glsl\""),
+            ],
+        );
+
+        let error = ExpandedSource::from_file(&dir.join("main.glsl")).unwrap_err();
+
+        match error {
+            ShaderError::IncludeError {
+                file,
+                line,
+                detail: IncludeErrorDetail::Cycle(cycle),
+            } => {
+                assert!(file.ends_with("b.glsl"));
+                assert_eq!(line, 2);
+                assert!(cycle.ends_with("a.glsl"));
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+
+    #[test]
+    fn lists_dependencies_up_to_a_missing_include() {
+        let dir = write_files(
+            "dependencies",
+            &[
+                ("main.glsl", "#include \"a.glsl\""),
+                ("a.glsl", "#include \"missing.glsl\""),
+            ],
+        );
+
+        let files = ExpandedSource::dependencies(&dir.join("main.glsl"));
+
+        assert_eq!(files.len(), 3);
+        assert!(files[1].ends_with("a.glsl"));
+        assert!(files[2].ends_with("missing.glsl"));
+    }
+
+    #[test]
+    fn rejects_malformed_directives() {
+        let dir = write_files("malformed", &[("main.glsl", "#include <a.glsl>")]);
+
+        assert!(matches!(
+            ExpandedSource::from_file(&dir.join("main.glsl")),
+            Err(ShaderError::IncludeError {
+                detail: IncludeErrorDetail::MalformedDirective(_),
+                ..
+            })
+        ));
+    }
+}
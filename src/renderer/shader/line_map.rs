@@ -0,0 +1,180 @@
+use std::path::PathBuf;
+
+/// Original file and line of every line of a preprocessed stage
+#[derive(Debug, Clone, Default)]
+pub struct LineMap {
+    /// (index into `ShaderSource::files`, 1-based line)
+    lines: Vec<(usize, usize)>,
+}
+
+/// Splits a driver log line into the line number it refers to, the severity
+/// when it comes before the location, and the message that follows it
+fn parse_log_line(text: &str) -> Option<(usize, &'static str, &str)> {
+    let digits = |s: &str| s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+
+    // AMD and Apple prefix the location with the severity:
+    // `ERROR: 0:12: 'x' : undeclared identifier`
+    for (prefix, severity) in [("ERROR: ", "error: "), ("WARNING: ", "warning: ")] {
+        if let Some(rest) = text.strip_prefix(prefix) {
+            let (line, message) = parse_colon_location(rest)?;
+            return Some((line, severity, message));
+        }
+    }
+
+    let string_end = digits(text);
+    if string_end == 0 {
+        return None;
+    }
+
+    let rest = &text[string_end..];
+
+    // NVIDIA: `0(12) : error C0000: syntax error`
+    if let Some(rest) = rest.strip_prefix('(') {
+        let line_end = digits(rest);
+        let line = rest[..line_end].parse().ok()?;
+        let message = rest[line_end..].strip_prefix(')')?;
+        let message = message.trim_start().strip_prefix(':')?;
+        return Some((line, "", message.trim_start()));
+    }
+
+    // Mesa: `0:12(5): error: syntax error`
+    let (line, message) = parse_colon_location(text)?;
+    Some((line, "", message))
+}
+
+/// Parses `<string>:<line>[(<column>)]: <message>`
+fn parse_colon_location(text: &str) -> Option<(usize, &str)> {
+    let (string, rest) = text.split_once(':')?;
+    string.trim().parse::<usize>().ok()?;
+
+    let line_end = rest
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(rest.len());
+    let line = rest[..line_end].parse().ok()?;
+    let mut rest = &rest[line_end..];
+
+    if let Some(column) = rest.strip_prefix('(') {
+        rest = &column[(column.find(')')? + 1)..];
+    }
+
+    let message = rest.strip_prefix(':')?;
+
+    Some((line, message.trim_start()))
+}
+
+impl LineMap {
+    pub fn push(&mut self, file: usize, line: usize) {
+        self.lines.push((file, line));
+    }
+
+    /// Makes room for `count` generated lines in front of the 0-based
+    /// `index`, reported at the location of the line before them
+    pub fn insert(&mut self, index: usize, count: usize) {
+        let location = match self.lines.get(index.saturating_sub(1)) {
+            Some(&location) => location,
+            None => return,
+        };
+
+        self.lines
+            .splice(index..index, std::iter::repeat_n(location, count));
+    }
+
+    /// Original location of a 1-based line of the stage
+    pub fn locate(&self, line: usize) -> Option<(usize, usize)> {
+        self.lines.get(line.checked_sub(1)?).copied()
+    }
+
+    /// Rewrites the locations of a compile log into `file:line: message`.
+    /// Lines that can't be mapped are left as they are
+    pub fn rewrite_log(&self, log: &str, files: &[PathBuf]) -> String {
+        log.lines()
+            .map(|text| {
+                let location = parse_log_line(text).and_then(|(line, severity, message)| {
+                    let (file, line) = self.locate(line)?;
+                    Some((files.get(file)?, line, severity, message))
+                });
+
+                match location {
+                    Some((file, line, severity, message)) => {
+                        format!("{}:{}: {}{}\n", file.display(), line, severity, message)
+                    }
+                    None => format!("{text}\n"),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line_map() -> (LineMap, Vec<PathBuf>) {
+        let mut map = LineMap::default();
+
+        for line in 1..=20 {
+            map.push(line % 2, line + 100);
+        }
+
+        (map, vec!["a.glsl".into(), "b.glsl".into()])
+    }
+
+    #[test]
+    fn parses_severity_first_format() {
+        assert_eq!(
+            parse_log_line("ERROR: 0:12: 'x' : undeclared identifier"),
+            Some((12, "error: ", "'x' : undeclared identifier"))
+        );
+        assert_eq!(
+            parse_log_line("WARNING: 0:3: unused"),
+            Some((3, "warning: ", "unused"))
+        );
+
+        let (map, files) = line_map();
+        assert_eq!(
+            map.rewrite_log("ERROR: 0:12: 'x' : undeclared identifier", &files),
+            "a.glsl:112: error: 'x' : undeclared identifier\n"
+        );
+    }
+
+    #[test]
+    fn parses_parenthesized_line_format() {
+        assert_eq!(
+            parse_log_line("0(7) : error C0000: syntax error"),
+            Some((7, "", "error C0000: syntax error"))
+        );
+
+        let (map, files) = line_map();
+        assert_eq!(
+            map.rewrite_log("0(7) : error C0000: syntax error", &files),
+            "b.glsl:107: error C0000: syntax error\n"
+        );
+    }
+
+    #[test]
+    fn parses_colon_line_with_column_format() {
+        assert_eq!(
+            parse_log_line("0:12(5): error: syntax error"),
+            Some((12, "", "error: syntax error"))
+        );
+
+        let (map, files) = line_map();
+        assert_eq!(
+            map.rewrite_log("0:12(5): error: syntax error", &files),
+            "a.glsl:112: error: syntax error\n"
+        );
+    }
+
+    #[test]
+    fn passes_unmatched_lines_through() {
+        let (map, files) = line_map();
+
+        for text in [
+            "Vertex shader failed to compile with the following errors:",
+            "ERROR: 1 compilation errors. No code generated.",
+            "0:99: beyond the end of the stage",
+        ] {
+            assert_eq!(map.rewrite_log(text, &files), format!("{text}\n"));
+        }
+    }
+}
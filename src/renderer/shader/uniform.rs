@@ -0,0 +1,166 @@
+use crate::renderer::math::{
+    matrix::{Mat3, Mat4},
+    vec::{Vec3, Vec4},
+};
+
+use super::ShaderUniformType;
+
+#[derive(Debug)]
+pub enum UniformError {
+    TypeMismatch {
+        name: String,
+        expected: ShaderUniformType,
+        got: ShaderUniformType,
+    },
+    /// More values than the uniform array holds
+    TooManyElements {
+        name: String,
+        size: usize,
+        got: usize,
+    },
+}
+
+/// A type that maps to a single GLSL uniform type
+pub trait UniformElement: Sized {
+    const TYPE: ShaderUniformType;
+
+    /// Uploads consecutive array elements, starting at `location`
+    ///
+    /// # Safety
+    /// `location` must be a uniform of `program` declared with `TYPE`
+    unsafe fn upload(program: u32, location: i32, values: &[Self]);
+}
+
+/// Anything that can be passed to `Shader::set_uniform`: single elements,
+/// slices and arrays of them
+pub trait UniformValue {
+    fn uniform_type(&self) -> ShaderUniformType;
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// # Safety
+    /// Same as `UniformElement::upload`
+    unsafe fn upload(&self, program: u32, location: i32);
+}
+
+impl<T: UniformElement> UniformValue for T {
+    fn uniform_type(&self) -> ShaderUniformType {
+        T::TYPE
+    }
+
+    fn len(&self) -> usize {
+        1
+    }
+
+    unsafe fn upload(&self, program: u32, location: i32) {
+        T::upload(program, location, std::slice::from_ref(self));
+    }
+}
+
+impl<T: UniformElement> UniformValue for [T] {
+    fn uniform_type(&self) -> ShaderUniformType {
+        T::TYPE
+    }
+
+    fn len(&self) -> usize {
+        <[T]>::len(self)
+    }
+
+    unsafe fn upload(&self, program: u32, location: i32) {
+        T::upload(program, location, self);
+    }
+}
+
+impl<T: UniformElement, const N: usize> UniformValue for [T; N] {
+    fn uniform_type(&self) -> ShaderUniformType {
+        T::TYPE
+    }
+
+    fn len(&self) -> usize {
+        N
+    }
+
+    unsafe fn upload(&self, program: u32, location: i32) {
+        T::upload(program, location, self);
+    }
+}
+
+impl UniformElement for f32 {
+    const TYPE: ShaderUniformType = ShaderUniformType::Float;
+
+    unsafe fn upload(program: u32, location: i32, values: &[Self]) {
+        gl::ProgramUniform1fv(program, location, values.len() as _, values.as_ptr());
+    }
+}
+
+impl UniformElement for i32 {
+    const TYPE: ShaderUniformType = ShaderUniformType::Int;
+
+    unsafe fn upload(program: u32, location: i32, values: &[Self]) {
+        gl::ProgramUniform1iv(program, location, values.len() as _, values.as_ptr());
+    }
+}
+
+impl UniformElement for u32 {
+    const TYPE: ShaderUniformType = ShaderUniformType::Uint;
+
+    unsafe fn upload(program: u32, location: i32, values: &[Self]) {
+        gl::ProgramUniform1uiv(program, location, values.len() as _, values.as_ptr());
+    }
+}
+
+impl UniformElement for bool {
+    const TYPE: ShaderUniformType = ShaderUniformType::Bool;
+
+    unsafe fn upload(program: u32, location: i32, values: &[Self]) {
+        let values: Vec<i32> = values.iter().map(|&value| value as i32).collect();
+        gl::ProgramUniform1iv(program, location, values.len() as _, values.as_ptr());
+    }
+}
+
+impl UniformElement for Vec3 {
+    const TYPE: ShaderUniformType = ShaderUniformType::Vec3;
+
+    unsafe fn upload(program: u32, location: i32, values: &[Self]) {
+        gl::ProgramUniform3fv(program, location, values.len() as _, values.as_ptr() as _);
+    }
+}
+
+impl UniformElement for Vec4 {
+    const TYPE: ShaderUniformType = ShaderUniformType::Vec4;
+
+    unsafe fn upload(program: u32, location: i32, values: &[Self]) {
+        gl::ProgramUniform4fv(program, location, values.len() as _, values.as_ptr() as _);
+    }
+}
+
+impl UniformElement for Mat3 {
+    const TYPE: ShaderUniformType = ShaderUniformType::Mat3;
+
+    unsafe fn upload(program: u32, location: i32, values: &[Self]) {
+        gl::ProgramUniformMatrix3fv(
+            program,
+            location,
+            values.len() as _,
+            gl::FALSE,
+            values.as_ptr() as _,
+        );
+    }
+}
+
+impl UniformElement for Mat4 {
+    const TYPE: ShaderUniformType = ShaderUniformType::Mat4;
+
+    unsafe fn upload(program: u32, location: i32, values: &[Self]) {
+        gl::ProgramUniformMatrix4fv(
+            program,
+            location,
+            values.len() as _,
+            gl::FALSE,
+            values.as_ptr() as _,
+        );
+    }
+}
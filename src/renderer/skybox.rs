@@ -0,0 +1,22 @@
+use super::{
+    cubemap::Cubemap,
+    mesh::{Mesh, MeshData},
+    shader::Shader,
+};
+
+/// An environment cubemap drawn behind everything else
+pub struct Skybox {
+    pub environment: Cubemap,
+    pub(super) mesh: Mesh,
+    pub shader: Shader,
+}
+
+impl Skybox {
+    pub fn new(environment: Cubemap, shader: Shader) -> Self {
+        Self {
+            environment,
+            mesh: Mesh::new(&MeshData::inward_cube()),
+            shader,
+        }
+    }
+}
@@ -1,53 +1,285 @@
-use crate::truevision::Targa;
+use std::ffi::CStr;
+
+use gl::types::GLenum;
+
+use crate::image::{Image, PixelFormat};
+
+use super::{
+    gl_state,
+    texture_unit::{SamplerSource, TextureUnit},
+};
+
+// From GL_EXT_texture_filter_anisotropic, core since 4.6
+const TEXTURE_MAX_ANISOTROPY: GLenum = 0x84FE;
+const MAX_TEXTURE_MAX_ANISOTROPY: GLenum = 0x84FF;
 
 pub struct Texture {
     raw: u32,
+    options: TextureOptions,
+    has_mipmaps: bool,
+}
+
+/// GPU storage used for floating-point images
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FloatPrecision {
+    Half,
+    Full,
+}
+
+/// How the color components of 8-bit images are interpreted. Color textures
+/// are sRGB, while data such as normal maps must stay linear
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorSpace {
+    Srgb,
+    Linear,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Filter {
+    Nearest,
+    Linear,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Wrap {
+    Repeat,
+    MirroredRepeat,
+    ClampToEdge,
+}
+
+impl Wrap {
+    fn raw(&self) -> GLenum {
+        match self {
+            Self::Repeat => gl::REPEAT,
+            Self::MirroredRepeat => gl::MIRRORED_REPEAT,
+            Self::ClampToEdge => gl::CLAMP_TO_EDGE,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextureOptions {
+    pub min_filter: Filter,
+    pub mag_filter: Filter,
+    /// Filter between mipmap levels, or `None` to sample the base level only
+    pub mipmap_filter: Option<Filter>,
+    pub wrap_s: Wrap,
+    pub wrap_t: Wrap,
+    /// Maximum anisotropy, clamped to what the driver supports. 1.0 disables
+    /// anisotropic filtering
+    pub anisotropy: f32,
+    pub float_precision: FloatPrecision,
+    pub color_space: ColorSpace,
+}
+
+impl Default for TextureOptions {
+    fn default() -> Self {
+        Self {
+            min_filter: Filter::Linear,
+            mag_filter: Filter::Linear,
+            mipmap_filter: Some(Filter::Linear),
+            wrap_s: Wrap::Repeat,
+            wrap_t: Wrap::Repeat,
+            anisotropy: 1.0,
+            float_precision: FloatPrecision::Half,
+            color_space: ColorSpace::Srgb,
+        }
+    }
+}
+
+impl TextureOptions {
+    fn raw_min_filter(&self) -> GLenum {
+        match (self.min_filter, self.mipmap_filter) {
+            (Filter::Nearest, None) => gl::NEAREST,
+            (Filter::Linear, None) => gl::LINEAR,
+            (Filter::Nearest, Some(Filter::Nearest)) => gl::NEAREST_MIPMAP_NEAREST,
+            (Filter::Linear, Some(Filter::Nearest)) => gl::LINEAR_MIPMAP_NEAREST,
+            (Filter::Nearest, Some(Filter::Linear)) => gl::NEAREST_MIPMAP_LINEAR,
+            (Filter::Linear, Some(Filter::Linear)) => gl::LINEAR_MIPMAP_LINEAR,
+        }
+    }
+
+    fn raw_mag_filter(&self) -> GLenum {
+        match self.mag_filter {
+            Filter::Nearest => gl::NEAREST,
+            Filter::Linear => gl::LINEAR,
+        }
+    }
+
+    /// Sets the sampling parameters of the texture bound to `target`
+    pub(super) unsafe fn apply(&self, target: GLenum) {
+        gl::TexParameteri(target, gl::TEXTURE_MIN_FILTER, self.raw_min_filter() as i32);
+        gl::TexParameteri(target, gl::TEXTURE_MAG_FILTER, self.raw_mag_filter() as i32);
+        gl::TexParameteri(target, gl::TEXTURE_WRAP_S, self.wrap_s.raw() as i32);
+        gl::TexParameteri(target, gl::TEXTURE_WRAP_T, self.wrap_t.raw() as i32);
+
+        if let Some(max) = max_anisotropy() {
+            let anisotropy = self.anisotropy.clamp(1.0, max);
+            gl::TexParameterf(target, TEXTURE_MAX_ANISOTROPY, anisotropy);
+        }
+    }
+}
+
+/// Largest anisotropy supported by the driver, if anisotropic filtering is
+/// available at all
+pub fn max_anisotropy() -> Option<f32> {
+    unsafe {
+        let mut count = 0;
+        gl::GetIntegerv(gl::NUM_EXTENSIONS, &mut count);
+
+        let supported = (0..count.max(0) as u32).any(|i| {
+            let name = gl::GetStringi(gl::EXTENSIONS, i);
+
+            if name.is_null() {
+                return false;
+            }
+
+            let name = CStr::from_ptr(name as *const _).to_bytes();
+            name == b"GL_EXT_texture_filter_anisotropic"
+                || name == b"GL_ARB_texture_filter_anisotropic"
+        });
+
+        if !supported {
+            return None;
+        }
+
+        let mut max = 1.0;
+        gl::GetFloatv(MAX_TEXTURE_MAX_ANISOTROPY, &mut max);
+
+        Some(max)
+    }
+}
+
+/// Internal format, pixel format and component type used to upload images.
+/// Float images are always linear, and there are no sRGB formats with less
+/// than three channels
+pub(super) fn upload_format(
+    format: PixelFormat,
+    precision: FloatPrecision,
+    color_space: ColorSpace,
+) -> (GLenum, GLenum, GLenum) {
+    let typ = if format.is_float() {
+        gl::FLOAT
+    } else {
+        gl::UNSIGNED_BYTE
+    };
+
+    let (rgb, rgba) = match color_space {
+        ColorSpace::Srgb => (gl::SRGB8, gl::SRGB8_ALPHA8),
+        ColorSpace::Linear => (gl::RGB8, gl::RGBA8),
+    };
+
+    let (internal_format, format) = match (format, precision) {
+        (PixelFormat::R8, _) => (gl::R8, gl::RED),
+        (PixelFormat::Rg8, _) => (gl::RG8, gl::RG),
+        (PixelFormat::Rgb8, _) => (rgb, gl::RGB),
+        (PixelFormat::Bgr8, _) => (rgb, gl::BGR),
+        (PixelFormat::Rgba8, _) => (rgba, gl::RGBA),
+        (PixelFormat::Bgra8, _) => (rgba, gl::BGRA),
+        (PixelFormat::Rgb32F, FloatPrecision::Half) => (gl::RGB16F, gl::RGB),
+        (PixelFormat::Rgb32F, FloatPrecision::Full) => (gl::RGB32F, gl::RGB),
+        (PixelFormat::Rgba32F, FloatPrecision::Half) => (gl::RGBA16F, gl::RGBA),
+        (PixelFormat::Rgba32F, FloatPrecision::Full) => (gl::RGBA32F, gl::RGBA),
+    };
+
+    (internal_format, format, typ)
 }
 
 impl Texture {
-    pub fn from_targa(targa: &Targa) -> Self {
+    /// Uploads an image with the default options
+    pub fn from_image(image: &Image) -> Self {
+        Self::from_image_with_options(image, TextureOptions::default())
+    }
+
+    pub fn from_image_with_options(image: &Image, options: TextureOptions) -> Self {
+        let bytes_per_pixel = image.format.bytes_per_pixel();
+
+        // Rows that are not a whole number of pixels apart can't be described
+        // with UNPACK_ROW_LENGTH
+        let packed;
+        let image = if image.stride.is_multiple_of(bytes_per_pixel) {
+            image
+        } else {
+            packed = image.to_packed();
+            &packed
+        };
+
+        let (internal_format, format, typ) =
+            upload_format(image.format, options.float_precision, options.color_space);
+
         unsafe {
             let mut raw = 0;
             gl::GenTextures(1, &mut raw);
 
-            gl::ActiveTexture(gl::TEXTURE0);
-            gl::BindTexture(gl::TEXTURE_2D, raw);
+            gl_state::bind_texture(TextureUnit::FIRST, gl::TEXTURE_2D, raw);
 
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+            gl::PixelStorei(gl::UNPACK_ROW_LENGTH, (image.stride / bytes_per_pixel) as _);
 
             gl::TexImage2D(
                 gl::TEXTURE_2D,
                 0,
-                gl::RGB as _,
-                targa.width as _,
-                targa.height as _,
+                internal_format as _,
+                image.width as _,
+                image.height as _,
                 0,
-                gl::BGR,
-                gl::UNSIGNED_BYTE,
-                targa.bytes.as_ptr() as _,
+                format,
+                typ,
+                image.bytes.as_ptr() as _,
             );
 
-            Self::unbind_slot(0);
+            gl::PixelStorei(gl::UNPACK_ROW_LENGTH, 0);
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+
+            gl_state::bind_texture(TextureUnit::FIRST, gl::TEXTURE_2D, 0);
+
+            let mut texture = Self {
+                raw,
+                options,
+                has_mipmaps: false,
+            };
 
-            return Self { raw };
+            texture.set_options(options);
+            texture
         }
     }
 
-    pub fn bind_slot(&self, slot: u32) {
-        unsafe {
-            let slot = gl::TEXTURE0 + slot; // warn: unsound
-            gl::ActiveTexture(slot);
-            gl::BindTexture(gl::TEXTURE_2D, self.raw);
-        }
+    pub fn options(&self) -> TextureOptions {
+        self.options
     }
 
-    pub fn unbind_slot(slot: u32) {
+    /// Applies new sampling options, generating mipmaps the first time they
+    /// are needed. The float precision and color space of an uploaded texture
+    /// can't change
+    pub fn set_options(&mut self, options: TextureOptions) {
+        self.bind_unit(TextureUnit::FIRST);
+
         unsafe {
-            let slot = gl::TEXTURE0 + slot; // warn: unsound
-            gl::ActiveTexture(slot);
-            gl::BindTexture(gl::TEXTURE_2D, 0);
+            if options.mipmap_filter.is_some() && !self.has_mipmaps {
+                gl::GenerateMipmap(gl::TEXTURE_2D);
+                self.has_mipmaps = true;
+            }
+
+            options.apply(gl::TEXTURE_2D);
         }
+
+        self.unbind_unit(TextureUnit::FIRST);
+
+        self.options = TextureOptions {
+            float_precision: self.options.float_precision,
+            color_space: self.options.color_space,
+            ..options
+        };
+    }
+}
+
+impl SamplerSource for Texture {
+    fn target(&self) -> GLenum {
+        gl::TEXTURE_2D
+    }
+
+    fn raw_texture(&self) -> u32 {
+        self.raw
     }
 }
 
@@ -56,5 +288,7 @@ impl Drop for Texture {
         unsafe {
             gl::DeleteTextures(1, &self.raw);
         }
+
+        gl_state::forget_texture(self.raw);
     }
 }
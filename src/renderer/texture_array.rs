@@ -0,0 +1,160 @@
+use gl::types::GLenum;
+
+use crate::image::Image;
+
+use super::{
+    gl_state,
+    texture::{upload_format, TextureOptions},
+    texture_unit::{SamplerSource, TextureUnit},
+};
+
+#[derive(Debug)]
+pub enum TextureArrayError {
+    Empty,
+    SizeMismatch,
+    FormatMismatch,
+}
+
+/// A `GL_TEXTURE_2D_ARRAY`, sampled in GLSL with a `sampler2DArray` and a
+/// layer index
+pub struct TextureArray {
+    raw: u32,
+    options: TextureOptions,
+    has_mipmaps: bool,
+    layers: usize,
+}
+
+impl TextureArray {
+    /// Uploads images of the same size and format, one per layer. Images of
+    /// different sizes can be brought together with `Image::resize`
+    pub fn from_images(
+        images: &[Image],
+        options: TextureOptions,
+    ) -> Result<Self, TextureArrayError> {
+        let first = images.first().ok_or(TextureArrayError::Empty)?;
+
+        for image in images {
+            if (image.width, image.height) != (first.width, first.height) {
+                return Err(TextureArrayError::SizeMismatch);
+            }
+            if image.format != first.format {
+                return Err(TextureArrayError::FormatMismatch);
+            }
+        }
+
+        let (internal_format, format, typ) =
+            upload_format(first.format, options.float_precision, options.color_space);
+
+        let mut raw = 0;
+
+        unsafe {
+            gl::GenTextures(1, &mut raw);
+
+            gl_state::bind_texture(TextureUnit::FIRST, gl::TEXTURE_2D_ARRAY, raw);
+
+            gl::TexImage3D(
+                gl::TEXTURE_2D_ARRAY,
+                0,
+                internal_format as _,
+                first.width as _,
+                first.height as _,
+                images.len() as _,
+                0,
+                format,
+                typ,
+                std::ptr::null(),
+            );
+
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+
+            for (layer, image) in images.iter().enumerate() {
+                let packed;
+                let image = if image.is_packed() {
+                    image
+                } else {
+                    packed = image.to_packed();
+                    &packed
+                };
+
+                gl::TexSubImage3D(
+                    gl::TEXTURE_2D_ARRAY,
+                    0,
+                    0,
+                    0,
+                    layer as _,
+                    image.width as _,
+                    image.height as _,
+                    1,
+                    format,
+                    typ,
+                    image.bytes.as_ptr() as _,
+                );
+            }
+
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+
+            gl_state::bind_texture(TextureUnit::FIRST, gl::TEXTURE_2D_ARRAY, 0);
+        }
+
+        let mut array = Self {
+            raw,
+            options,
+            has_mipmaps: false,
+            layers: images.len(),
+        };
+
+        array.set_options(options);
+
+        Ok(array)
+    }
+
+    pub fn layers(&self) -> usize {
+        self.layers
+    }
+
+    pub fn options(&self) -> TextureOptions {
+        self.options
+    }
+
+    /// Same as `Texture::set_options`, for every layer at once
+    pub fn set_options(&mut self, options: TextureOptions) {
+        self.bind_unit(TextureUnit::FIRST);
+
+        unsafe {
+            if options.mipmap_filter.is_some() && !self.has_mipmaps {
+                gl::GenerateMipmap(gl::TEXTURE_2D_ARRAY);
+                self.has_mipmaps = true;
+            }
+
+            options.apply(gl::TEXTURE_2D_ARRAY);
+        }
+
+        self.unbind_unit(TextureUnit::FIRST);
+
+        self.options = TextureOptions {
+            float_precision: self.options.float_precision,
+            color_space: self.options.color_space,
+            ..options
+        };
+    }
+}
+
+impl SamplerSource for TextureArray {
+    fn target(&self) -> GLenum {
+        gl::TEXTURE_2D_ARRAY
+    }
+
+    fn raw_texture(&self) -> u32 {
+        self.raw
+    }
+}
+
+impl Drop for TextureArray {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteTextures(1, &self.raw);
+        }
+
+        gl_state::forget_texture(self.raw);
+    }
+}
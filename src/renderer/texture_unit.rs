@@ -0,0 +1,87 @@
+use std::{cell::RefCell, sync::OnceLock};
+
+use gl::types::GLenum;
+
+use super::gl_state;
+
+/// A texture image unit known to exist on the current driver
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TextureUnit(u32);
+
+impl TextureUnit {
+    /// Always available, used for uploads and parameter changes
+    pub const FIRST: Self = Self(0);
+
+    /// Returns `None` past `GL_MAX_COMBINED_TEXTURE_IMAGE_UNITS`
+    pub fn new(index: u32) -> Option<Self> {
+        if index < max_texture_units() {
+            Some(Self(index))
+        } else {
+            None
+        }
+    }
+
+    pub fn index(&self) -> u32 {
+        self.0
+    }
+
+    /// Makes this unit the target of texture binding calls
+    pub fn activate(&self) {
+        gl_state::active_texture(*self);
+    }
+}
+
+/// Number of texture units usable across all shader stages
+pub fn max_texture_units() -> u32 {
+    static MAX: OnceLock<u32> = OnceLock::new();
+
+    *MAX.get_or_init(|| {
+        let mut max = 0;
+        unsafe { gl::GetIntegerv(gl::MAX_COMBINED_TEXTURE_IMAGE_UNITS, &mut max) };
+        max.max(0) as u32
+    })
+}
+
+/// Anything that can be bound to a sampler uniform
+pub trait SamplerSource {
+    fn target(&self) -> GLenum;
+    fn raw_texture(&self) -> u32;
+
+    fn bind_unit(&self, unit: TextureUnit) {
+        gl_state::bind_texture(unit, self.target(), self.raw_texture());
+    }
+
+    fn unbind_unit(&self, unit: TextureUnit) {
+        gl_state::bind_texture(unit, self.target(), 0);
+    }
+}
+
+/// For textures shared with a material while their options stay editable
+impl<T: SamplerSource> SamplerSource for RefCell<T> {
+    fn target(&self) -> GLenum {
+        self.borrow().target()
+    }
+
+    fn raw_texture(&self) -> u32 {
+        self.borrow().raw_texture()
+    }
+}
+
+/// Hands out a unit per sampler, in order
+#[derive(Debug, Default)]
+pub struct TextureUnitAllocator {
+    next: u32,
+}
+
+impl TextureUnitAllocator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns `None` once every unit has been handed out
+    pub fn allocate(&mut self) -> Option<TextureUnit> {
+        let unit = TextureUnit::new(self.next)?;
+        self.next += 1;
+        Some(unit)
+    }
+}
@@ -0,0 +1,273 @@
+use self::std140::Std140Writer;
+
+use super::{
+    camera::Camera,
+    math::{matrix::Mat4, vec::Vec3},
+};
+
+pub mod std140;
+
+/// Fixed binding points of the uniform blocks shared by every shader.
+/// Shaders declare them by name, see `res/shaders/include/blocks.glsl`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UniformBlockBinding {
+    Camera,
+    Lights,
+}
+
+impl UniformBlockBinding {
+    pub fn from_block_name(name: &str) -> Option<Self> {
+        match name {
+            "CameraBlock" => Some(Self::Camera),
+            "LightsBlock" => Some(Self::Lights),
+            _ => None,
+        }
+    }
+
+    pub fn index(&self) -> u32 {
+        *self as u32
+    }
+
+    /// std140 size of the block, which the shader declaration has to match
+    pub fn block_size(&self) -> usize {
+        match self {
+            Self::Camera => CameraBlock::SIZE,
+            Self::Lights => LightsBlock::SIZE,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct UniformBuffer {
+    raw: u32,
+    size: usize,
+}
+
+impl UniformBuffer {
+    pub fn new(size: usize) -> Self {
+        let mut raw = 0;
+
+        unsafe {
+            gl::GenBuffers(1, &mut raw);
+            gl::BindBuffer(gl::UNIFORM_BUFFER, raw);
+            gl::BufferData(
+                gl::UNIFORM_BUFFER,
+                size as _,
+                std::ptr::null(),
+                gl::DYNAMIC_DRAW,
+            );
+            gl::BindBuffer(gl::UNIFORM_BUFFER, 0);
+        }
+
+        Self { raw, size }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Replaces the start of the buffer, which grows if `bytes` don't fit
+    pub fn update(&mut self, bytes: &[u8]) {
+        unsafe {
+            gl::BindBuffer(gl::UNIFORM_BUFFER, self.raw);
+
+            if bytes.len() > self.size {
+                gl::BufferData(
+                    gl::UNIFORM_BUFFER,
+                    bytes.len() as _,
+                    bytes.as_ptr() as _,
+                    gl::DYNAMIC_DRAW,
+                );
+                self.size = bytes.len();
+            } else {
+                gl::BufferSubData(gl::UNIFORM_BUFFER, 0, bytes.len() as _, bytes.as_ptr() as _);
+            }
+
+            gl::BindBuffer(gl::UNIFORM_BUFFER, 0);
+        }
+    }
+
+    /// Makes the buffer the source of every block bound to `binding`
+    pub fn bind(&self, binding: UniformBlockBinding) {
+        unsafe { gl::BindBufferBase(gl::UNIFORM_BUFFER, binding.index(), self.raw) }
+    }
+}
+
+impl Drop for UniformBuffer {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteBuffers(1, &self.raw) }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CameraBlock {
+    pub view: Mat4,
+    pub projection: Mat4,
+    pub view_projection: Mat4,
+    pub position: Vec3,
+}
+
+impl CameraBlock {
+    /// 3 mat4 and a vec3, padded to 16 bytes
+    pub const SIZE: usize = 3 * 64 + 16;
+
+    pub fn from_camera(camera: &Camera) -> Self {
+        Self {
+            view: camera.view_matrix(),
+            projection: camera.projection_matrix(),
+            view_projection: camera.view_projection_matrix(),
+            position: camera.transform.position,
+        }
+    }
+
+    pub fn to_std140(&self) -> Vec<u8> {
+        let mut writer = Std140Writer::new();
+
+        writer
+            .write_mat4(&self.view)
+            .write_mat4(&self.projection)
+            .write_mat4(&self.view_projection)
+            .write_vec3(self.position);
+
+        writer.finish()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct DirectionalLight {
+    /// Towards the light
+    pub direction: Vec3,
+    pub color: Vec3,
+}
+
+#[derive(Debug, Clone)]
+pub struct LightsBlock {
+    /// Only the first `MAX_LIGHTS` are used
+    pub lights: Vec<DirectionalLight>,
+    pub ambient: Vec3,
+}
+
+impl LightsBlock {
+    /// Length of the `lights` array in GLSL
+    pub const MAX_LIGHTS: usize = 4;
+
+    /// An array of structs of 2 vec3, then a vec3 and an int sharing 16 bytes
+    pub const SIZE: usize = Self::MAX_LIGHTS * 32 + 16;
+
+    pub fn to_std140(&self) -> Vec<u8> {
+        let mut writer = Std140Writer::new();
+
+        let unused = DirectionalLight {
+            direction: Vec3(0.0, 0.0, 0.0),
+            color: Vec3(0.0, 0.0, 0.0),
+        };
+
+        let lights = self.lights.iter().chain(std::iter::repeat(&unused));
+
+        for light in lights.take(Self::MAX_LIGHTS) {
+            writer.write_struct(|writer| {
+                writer.write_vec3(light.direction).write_vec3(light.color);
+            });
+        }
+
+        writer
+            .write_vec3(self.ambient)
+            .write_i32(self.lights.len().min(Self::MAX_LIGHTS) as i32);
+
+        writer.finish()
+    }
+}
+
+/// The shared blocks, updated and bound once per frame
+#[derive(Debug)]
+pub struct FrameUniforms {
+    camera: UniformBuffer,
+    lights: UniformBuffer,
+}
+
+impl FrameUniforms {
+    pub fn new() -> Self {
+        Self {
+            camera: UniformBuffer::new(CameraBlock::SIZE),
+            lights: UniformBuffer::new(LightsBlock::SIZE),
+        }
+    }
+
+    pub fn update(&mut self, camera: &Camera, lights: &LightsBlock) {
+        self.camera
+            .update(&CameraBlock::from_camera(camera).to_std140());
+        self.lights.update(&lights.to_std140());
+
+        self.camera.bind(UniformBlockBinding::Camera);
+        self.lights.bind(UniformBlockBinding::Lights);
+    }
+}
+
+impl Default for FrameUniforms {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::renderer::math::vec::Vec4;
+
+    use super::*;
+
+    fn f32_at(bytes: &[u8], offset: usize) -> f32 {
+        f32::from_ne_bytes(bytes[offset..(offset + 4)].try_into().unwrap())
+    }
+
+    #[test]
+    fn lays_out_camera_block() {
+        let block = CameraBlock {
+            view: Mat4::from_cols(
+                Vec4(1.0, 0.0, 0.0, 0.0),
+                Vec4(0.0, 1.0, 0.0, 0.0),
+                Vec4(0.0, 0.0, 1.0, 0.0),
+                Vec4(0.0, 0.0, 0.0, 1.0),
+            ),
+            projection: Mat4::ZERO,
+            view_projection: Mat4::ZERO,
+            position: Vec3(5.0, 6.0, 7.0),
+        };
+
+        let bytes = block.to_std140();
+
+        assert_eq!(CameraBlock::SIZE, 208);
+        assert_eq!(bytes.len(), CameraBlock::SIZE);
+        assert_eq!(f32_at(&bytes, 60), 1.0);
+        assert_eq!(f32_at(&bytes, 192), 5.0);
+        assert_eq!(f32_at(&bytes, 200), 7.0);
+    }
+
+    #[test]
+    fn lays_out_lights_block() {
+        let block = LightsBlock {
+            lights: vec![
+                DirectionalLight {
+                    direction: Vec3(1.0, 0.0, 0.0),
+                    color: Vec3(0.5, 0.5, 0.5),
+                };
+                5
+            ],
+            ambient: Vec3(0.2, 0.3, 0.4),
+        };
+
+        let bytes = block.to_std140();
+
+        assert_eq!(LightsBlock::SIZE, 144);
+        assert_eq!(bytes.len(), LightsBlock::SIZE);
+
+        for light in 0..LightsBlock::MAX_LIGHTS {
+            assert_eq!(f32_at(&bytes, light * 32), 1.0);
+            assert_eq!(f32_at(&bytes, light * 32 + 16), 0.5);
+        }
+
+        assert_eq!(f32_at(&bytes, 128), 0.2);
+        assert_eq!(f32_at(&bytes, 136), 0.4);
+        // The count shares the ambient vec3's last 4 bytes, capped at MAX_LIGHTS
+        assert_eq!(bytes[140..144], 4i32.to_ne_bytes());
+    }
+}
@@ -0,0 +1,160 @@
+use crate::renderer::math::{
+    matrix::Mat4,
+    vec::{Vec3, Vec4},
+};
+
+/// Lays values out following the std140 rules, to match a
+/// `layout (std140) uniform` block declared in GLSL
+#[derive(Debug, Default)]
+pub struct Std140Writer {
+    bytes: Vec<u8>,
+}
+
+impl Std140Writer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Offset of the next value, before alignment
+    pub fn offset(&self) -> usize {
+        self.bytes.len()
+    }
+
+    fn align(&mut self, alignment: usize) {
+        let aligned = self.bytes.len().next_multiple_of(alignment);
+        self.bytes.resize(aligned, 0);
+    }
+
+    fn push_scalar(&mut self, bytes: [u8; 4]) -> &mut Self {
+        self.align(4);
+        self.bytes.extend_from_slice(&bytes);
+        self
+    }
+
+    fn push_vector(&mut self, values: &[f32]) -> &mut Self {
+        self.align(16);
+
+        for value in values {
+            self.bytes.extend_from_slice(&value.to_ne_bytes());
+        }
+
+        self
+    }
+
+    pub fn write_f32(&mut self, value: f32) -> &mut Self {
+        self.push_scalar(value.to_ne_bytes())
+    }
+
+    pub fn write_i32(&mut self, value: i32) -> &mut Self {
+        self.push_scalar(value.to_ne_bytes())
+    }
+
+    pub fn write_u32(&mut self, value: u32) -> &mut Self {
+        self.push_scalar(value.to_ne_bytes())
+    }
+
+    /// A vec3 is aligned like a vec4, but a scalar can follow it in the
+    /// remaining 4 bytes
+    pub fn write_vec3(&mut self, value: Vec3) -> &mut Self {
+        self.push_vector(&[value.0, value.1, value.2])
+    }
+
+    pub fn write_vec4(&mut self, value: Vec4) -> &mut Self {
+        self.push_vector(&[value.0, value.1, value.2, value.3])
+    }
+
+    /// Column-major, each column as a vec4
+    pub fn write_mat4(&mut self, value: &Mat4) -> &mut Self {
+        for column in [value.x_axis, value.y_axis, value.z_axis, value.w_axis] {
+            self.write_vec4(column);
+        }
+
+        self
+    }
+
+    /// Structs, and so the elements of arrays of structs, start and end on
+    /// a 16 bytes boundary
+    pub fn write_struct(&mut self, write: impl FnOnce(&mut Self)) -> &mut Self {
+        self.align(16);
+        write(self);
+        self.align(16);
+        self
+    }
+
+    /// The bytes of the block, padded to its std140 size
+    pub fn finish(mut self) -> Vec<u8> {
+        self.align(16);
+        self.bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn f32_at(bytes: &[u8], offset: usize) -> f32 {
+        f32::from_ne_bytes(bytes[offset..(offset + 4)].try_into().unwrap())
+    }
+
+    #[test]
+    fn packs_a_scalar_after_a_vec3() {
+        let mut writer = Std140Writer::new();
+        writer.write_vec3(Vec3(1.0, 2.0, 3.0)).write_f32(4.0);
+
+        let bytes = writer.finish();
+        assert_eq!(bytes.len(), 16);
+        assert_eq!(f32_at(&bytes, 8), 3.0);
+        assert_eq!(f32_at(&bytes, 12), 4.0);
+    }
+
+    #[test]
+    fn aligns_vectors_to_16_bytes() {
+        let mut writer = Std140Writer::new();
+        writer.write_f32(1.0).write_vec3(Vec3(2.0, 3.0, 4.0));
+
+        assert_eq!(writer.offset(), 28);
+
+        let bytes = writer.finish();
+        assert_eq!(bytes.len(), 32);
+        assert_eq!(f32_at(&bytes, 4), 0.0);
+        assert_eq!(f32_at(&bytes, 16), 2.0);
+    }
+
+    #[test]
+    fn writes_mat4_columns_as_vec4() {
+        let matrix = Mat4::from_cols(
+            Vec4(1.0, 2.0, 3.0, 4.0),
+            Vec4(5.0, 6.0, 7.0, 8.0),
+            Vec4(9.0, 10.0, 11.0, 12.0),
+            Vec4(13.0, 14.0, 15.0, 16.0),
+        );
+
+        let mut writer = Std140Writer::new();
+        writer.write_f32(0.0).write_mat4(&matrix);
+
+        let bytes = writer.finish();
+        assert_eq!(bytes.len(), 80);
+
+        for column in 0..4 {
+            assert_eq!(f32_at(&bytes, 16 + column * 16), (column * 4 + 1) as f32);
+        }
+    }
+
+    #[test]
+    fn gives_struct_array_elements_a_16_byte_stride() {
+        let mut writer = Std140Writer::new();
+
+        for i in 0..3 {
+            writer.write_struct(|writer| {
+                writer.write_i32(i);
+            });
+        }
+
+        writer.write_u32(7);
+
+        let bytes = writer.finish();
+        assert_eq!(bytes.len(), 64);
+        assert_eq!(bytes[32..36], 2i32.to_ne_bytes());
+        assert_eq!(bytes[48..52], 7u32.to_ne_bytes());
+    }
+}
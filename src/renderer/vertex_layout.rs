@@ -0,0 +1,525 @@
+use gl::types::GLenum;
+
+use super::{mesh::MeshData, shader::ShaderUniformType};
+
+/// A per-vertex stream of `MeshData`, at a fixed location that shaders
+/// declare in `res/shaders/include/vertex_layout.glsl`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VertexChannel {
+    Position,
+    Normal,
+    Color,
+    Uv,
+    Tangent,
+    Uv2,
+    BoneIndices,
+    BoneWeights,
+}
+
+impl VertexChannel {
+    pub const ALL: [Self; 8] = [
+        Self::Position,
+        Self::Normal,
+        Self::Color,
+        Self::Uv,
+        Self::Tangent,
+        Self::Uv2,
+        Self::BoneIndices,
+        Self::BoneWeights,
+    ];
+
+    pub fn location(&self) -> u32 {
+        *self as u32
+    }
+
+    pub fn from_location(location: u32) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|channel| channel.location() == location)
+    }
+
+    /// Name of the vertex shader input
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Position => "aPos",
+            Self::Normal => "aNormal",
+            Self::Color => "aColor",
+            Self::Uv => "aUV",
+            Self::Tangent => "aTangent",
+            Self::Uv2 => "aUV2",
+            Self::BoneIndices => "aBoneIndices",
+            Self::BoneWeights => "aBoneWeights",
+        }
+    }
+
+    /// Floats per vertex in `MeshData`
+    pub fn size(&self) -> usize {
+        match self {
+            Self::Position | Self::Normal | Self::Color => 3,
+            Self::Uv | Self::Uv2 => 2,
+            // xyz and the handedness of the bitangent in w
+            Self::Tangent => 4,
+            Self::BoneIndices | Self::BoneWeights => 4,
+        }
+    }
+
+    /// Value of components the mesh doesn't provide, and of the whole
+    /// channel when a mesh doesn't have it
+    fn default_value(&self, component: usize) -> f32 {
+        match (self, component) {
+            (Self::Color, _) => 1.0,
+            (Self::Tangent, 0) | (Self::Tangent, 3) => 1.0,
+            _ => 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComponentType {
+    Float,
+    HalfFloat,
+    Byte,
+    UnsignedByte,
+    Short,
+    UnsignedShort,
+    Int,
+    UnsignedInt,
+}
+
+impl ComponentType {
+    pub fn size(&self) -> usize {
+        match self {
+            Self::Byte | Self::UnsignedByte => 1,
+            Self::HalfFloat | Self::Short | Self::UnsignedShort => 2,
+            Self::Float | Self::Int | Self::UnsignedInt => 4,
+        }
+    }
+
+    pub fn raw(&self) -> GLenum {
+        match self {
+            Self::Float => gl::FLOAT,
+            Self::HalfFloat => gl::HALF_FLOAT,
+            Self::Byte => gl::BYTE,
+            Self::UnsignedByte => gl::UNSIGNED_BYTE,
+            Self::Short => gl::SHORT,
+            Self::UnsignedShort => gl::UNSIGNED_SHORT,
+            Self::Int => gl::INT,
+            Self::UnsignedInt => gl::UNSIGNED_INT,
+        }
+    }
+
+    fn is_integer(&self) -> bool {
+        !matches!(self, Self::Float | Self::HalfFloat)
+    }
+
+    fn is_signed(&self) -> bool {
+        matches!(self, Self::Byte | Self::Short | Self::Int)
+    }
+
+    fn write(&self, value: f32, normalized: bool, bytes: &mut Vec<u8>) {
+        // Normalized integers map [0, 1], or [-1, 1] when signed, to their
+        // whole range
+        let integer = |max: f64| {
+            let value = value as f64;
+
+            match (normalized, self.is_signed()) {
+                (true, true) => (value.clamp(-1.0, 1.0) * max).round(),
+                (true, false) => (value.clamp(0.0, 1.0) * max).round(),
+                (false, _) => value.round(),
+            }
+        };
+
+        match self {
+            Self::Float => bytes.extend_from_slice(&value.to_ne_bytes()),
+            Self::HalfFloat => bytes.extend_from_slice(&f32_to_f16(value).to_ne_bytes()),
+            Self::Byte => bytes.extend_from_slice(&(integer(127.0) as i8).to_ne_bytes()),
+            Self::UnsignedByte => bytes.push(integer(255.0) as u8),
+            Self::Short => bytes.extend_from_slice(&(integer(32767.0) as i16).to_ne_bytes()),
+            Self::UnsignedShort => {
+                bytes.extend_from_slice(&(integer(65535.0) as u16).to_ne_bytes())
+            }
+            Self::Int => bytes.extend_from_slice(&(integer(2147483647.0) as i32).to_ne_bytes()),
+            Self::UnsignedInt => {
+                bytes.extend_from_slice(&(integer(4294967295.0) as u32).to_ne_bytes())
+            }
+        }
+    }
+}
+
+/// Rounds to the nearest half float, overflowing to infinity
+fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    // Infinity and NaN
+    if exponent == 0xff {
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+
+    let exponent = exponent - 127 + 15;
+
+    if exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+
+    // Subnormal, or too small even for that
+    if exponent <= 0 {
+        if exponent < -10 {
+            return sign;
+        }
+
+        let mantissa = mantissa | 0x80_0000;
+        let shift = (14 - exponent) as u32;
+        let round = (mantissa >> (shift - 1)) & 1;
+
+        return sign | ((mantissa >> shift) + round) as u16;
+    }
+
+    // A carry out of the mantissa correctly bumps the exponent
+    let half = sign | ((exponent as u16) << 10) | (mantissa >> 13) as u16;
+    half + ((mantissa >> 12) & 1) as u16
+}
+
+/// How one channel is stored in the vertex buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VertexAttribute {
+    channel: VertexChannel,
+    components: usize,
+    component_type: ComponentType,
+    normalized: bool,
+}
+
+impl VertexAttribute {
+    /// `normalized` integer components are read as floats in [0, 1], or
+    /// [-1, 1] when signed. Integers that aren't normalized reach the shader
+    /// as integers, in `ivec` or `uvec` inputs
+    ///
+    /// # Panics
+    ///
+    /// If `components` isn't between 1 and 4
+    pub const fn new(
+        channel: VertexChannel,
+        components: usize,
+        component_type: ComponentType,
+        normalized: bool,
+    ) -> Self {
+        assert!(
+            components >= 1 && components <= 4,
+            "vertex attributes have 1 to 4 components"
+        );
+
+        Self {
+            channel,
+            components,
+            component_type,
+            normalized,
+        }
+    }
+
+    pub fn channel(&self) -> VertexChannel {
+        self.channel
+    }
+
+    pub fn components(&self) -> usize {
+        self.components
+    }
+
+    pub fn component_type(&self) -> ComponentType {
+        self.component_type
+    }
+
+    pub fn normalized(&self) -> bool {
+        self.normalized
+    }
+
+    pub fn location(&self) -> u32 {
+        self.channel.location()
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.channel.name()
+    }
+
+    fn is_integer(&self) -> bool {
+        self.component_type.is_integer() && !self.normalized
+    }
+
+    /// Bytes per vertex, padded to 4 bytes
+    pub fn size(&self) -> usize {
+        (self.components * self.component_type.size()).next_multiple_of(4)
+    }
+
+    /// Type of the shader input that reads the attribute without conversion
+    pub fn shader_type(&self) -> ShaderUniformType {
+        use ShaderUniformType::*;
+
+        let types = match (self.is_integer(), self.component_type.is_signed()) {
+            (false, _) => [Float, Vec2, Vec3, Vec4],
+            (true, true) => [Int, IVec2, IVec3, IVec4],
+            (true, false) => [Uint, UVec2, UVec3, UVec4],
+        };
+
+        types[self.components - 1]
+    }
+}
+
+/// The interleaved format of a vertex buffer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VertexLayout {
+    attributes: Vec<VertexAttribute>,
+}
+
+impl VertexLayout {
+    pub fn new(attributes: Vec<VertexAttribute>) -> Self {
+        Self { attributes }
+    }
+
+    pub fn attributes(&self) -> &[VertexAttribute] {
+        &self.attributes
+    }
+
+    pub fn stride(&self) -> usize {
+        self.attributes.iter().map(VertexAttribute::size).sum()
+    }
+
+    fn offsets(&self) -> impl Iterator<Item = usize> + '_ {
+        self.attributes.iter().scan(0, |offset, attribute| {
+            let current = *offset;
+            *offset += attribute.size();
+            Some(current)
+        })
+    }
+
+    /// Interleaves the channels of every vertex of `data`. Channels or
+    /// components `data` doesn't have get default values
+    pub fn encode(&self, data: &MeshData) -> Vec<u8> {
+        let vertex_count = data.vertex_count();
+        let mut bytes = Vec::with_capacity(vertex_count * self.stride());
+
+        for vertex in 0..vertex_count {
+            for attribute in &self.attributes {
+                let channel = attribute.channel;
+                let values = data.channel(channel);
+                let start = bytes.len();
+
+                for component in 0..attribute.components {
+                    let value = match component < channel.size() {
+                        true => values.get(vertex * channel.size() + component).copied(),
+                        false => None,
+                    };
+                    let value = value.unwrap_or(channel.default_value(component));
+
+                    attribute
+                        .component_type
+                        .write(value, attribute.normalized, &mut bytes);
+                }
+
+                bytes.resize(start + attribute.size(), 0);
+            }
+        }
+
+        bytes
+    }
+
+    /// Points the attributes at the buffer bound to `GL_ARRAY_BUFFER`, in
+    /// the bound vertex array. Channels the layout doesn't have read their
+    /// default value instead, e.g. white colors
+    pub(super) unsafe fn apply(&self) {
+        let stride = self.stride();
+
+        for (attribute, offset) in self.attributes.iter().zip(self.offsets()) {
+            let location = attribute.location();
+
+            if attribute.is_integer() {
+                gl::VertexAttribIPointer(
+                    location,
+                    attribute.components as _,
+                    attribute.component_type.raw(),
+                    stride as _,
+                    offset as _,
+                );
+            } else {
+                gl::VertexAttribPointer(
+                    location,
+                    attribute.components as _,
+                    attribute.component_type.raw(),
+                    attribute.normalized as _,
+                    stride as _,
+                    offset as _,
+                );
+            }
+
+            gl::EnableVertexAttribArray(location);
+        }
+
+        // Disabled arrays read the current generic attribute, which is context
+        // state and (0, 0, 0, 1) unless set
+        for channel in VertexChannel::ALL {
+            if self
+                .attributes
+                .iter()
+                .any(|attribute| attribute.channel == channel)
+            {
+                continue;
+            }
+
+            let location = channel.location();
+            gl::DisableVertexAttribArray(location);
+
+            if channel == VertexChannel::BoneIndices {
+                gl::VertexAttribI4ui(location, 0, 0, 0, 0);
+            } else {
+                let value = |component| channel.default_value(component);
+                gl::VertexAttrib4f(location, value(0), value(1), value(2), value(3));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(attribute: VertexAttribute, data: &MeshData) -> Vec<u8> {
+        VertexLayout::new(vec![attribute]).encode(data)
+    }
+
+    fn mesh_data(positions: Vec<f32>) -> MeshData {
+        let mut data = MeshData::new();
+        data.positions = positions;
+        data
+    }
+
+    #[test]
+    fn rounds_to_nearest_half_float() {
+        assert_eq!(f32_to_f16(1.0), 0x3c00);
+        assert_eq!(f32_to_f16(-2.0), 0xc000);
+        assert_eq!(f32_to_f16(0.333333), 0x3555);
+        // Halfway up to the next mantissa step rounds up, and a carry out
+        // of the mantissa bumps the exponent
+        assert_eq!(f32_to_f16(1.0 + 1.0 / 2048.0), 0x3c01);
+        assert_eq!(f32_to_f16(2.0 - 1.0 / 4096.0), 0x4000);
+        assert_eq!(f32_to_f16(65504.0), 0x7bff);
+    }
+
+    #[test]
+    fn converts_subnormals() {
+        // Smallest normal, then subnormal multiples of 2^-24
+        assert_eq!(f32_to_f16(6.1035156e-5), 0x0400);
+        assert_eq!(f32_to_f16(2.0f32.powi(-24)), 0x0001);
+        assert_eq!(f32_to_f16(1e-7), 0x0002);
+        assert_eq!(f32_to_f16(2.0f32.powi(-26)), 0x0000);
+        assert_eq!(f32_to_f16(-2.0f32.powi(-30)), 0x8000);
+    }
+
+    #[test]
+    fn overflows_to_infinity_and_keeps_nan() {
+        assert_eq!(f32_to_f16(70000.0), 0x7c00);
+        assert_eq!(f32_to_f16(-1e10), 0xfc00);
+        assert_eq!(f32_to_f16(f32::INFINITY), 0x7c00);
+        assert_eq!(f32_to_f16(f32::NEG_INFINITY), 0xfc00);
+
+        let nan = f32_to_f16(f32::NAN);
+        assert_eq!(nan & 0x7c00, 0x7c00);
+        assert_ne!(nan & 0x03ff, 0);
+    }
+
+    #[test]
+    fn clamps_normalized_integers() {
+        let write = |typ: ComponentType, value: f32, normalized: bool| {
+            let mut bytes = vec![];
+            typ.write(value, normalized, &mut bytes);
+            bytes
+        };
+
+        assert_eq!(write(ComponentType::UnsignedByte, 0.5, true), [128]);
+        assert_eq!(write(ComponentType::UnsignedByte, 2.0, true), [255]);
+        assert_eq!(write(ComponentType::UnsignedByte, -1.0, true), [0]);
+        assert_eq!(
+            write(ComponentType::Byte, -2.0, true),
+            (-127i8).to_ne_bytes()
+        );
+        assert_eq!(write(ComponentType::Byte, 1.0, true), 127i8.to_ne_bytes());
+        assert_eq!(
+            write(ComponentType::UnsignedShort, 1.0, true),
+            u16::MAX.to_ne_bytes()
+        );
+        assert_eq!(
+            write(ComponentType::Short, -0.5, true),
+            (-16384i16).to_ne_bytes()
+        );
+        // Plain integers keep their value, saturating at the type range
+        assert_eq!(write(ComponentType::UnsignedByte, 7.0, false), [7]);
+        assert_eq!(write(ComponentType::UnsignedByte, 300.0, false), [255]);
+    }
+
+    #[test]
+    fn pads_attributes_to_4_bytes() {
+        let color =
+            VertexAttribute::new(VertexChannel::Color, 3, ComponentType::UnsignedByte, true);
+        let uv = VertexAttribute::new(VertexChannel::Uv, 2, ComponentType::HalfFloat, false);
+        let position =
+            VertexAttribute::new(VertexChannel::Position, 3, ComponentType::Float, false);
+
+        assert_eq!(color.size(), 4);
+        assert_eq!(uv.size(), 4);
+        assert_eq!(position.size(), 12);
+
+        let layout = VertexLayout::new(vec![color, position]);
+        assert_eq!(layout.stride(), 16);
+        assert_eq!(layout.offsets().collect::<Vec<_>>(), [0, 4]);
+
+        let mut data = mesh_data(vec![1.0, 2.0, 3.0]);
+        data.colors = vec![1.0, 0.0, 0.5];
+
+        let bytes = layout.encode(&data);
+        assert_eq!(bytes.len(), 16);
+        assert_eq!(bytes[..4], [255, 0, 128, 0]);
+        assert_eq!(bytes[4..8], 1.0f32.to_ne_bytes());
+    }
+
+    #[test]
+    fn fills_missing_channels_with_defaults() {
+        let data = mesh_data(vec![0.0; 6]);
+
+        let color =
+            VertexAttribute::new(VertexChannel::Color, 4, ComponentType::UnsignedByte, true);
+        assert_eq!(encode(color, &data), [255; 8]);
+
+        let tangent = VertexAttribute::new(VertexChannel::Tangent, 4, ComponentType::Byte, true);
+        assert_eq!(encode(tangent, &data)[..4], [127, 0, 0, 127]);
+    }
+
+    #[test]
+    fn maps_attributes_to_shader_types() {
+        let attribute = |components, typ, normalized| {
+            VertexAttribute::new(VertexChannel::BoneIndices, components, typ, normalized)
+                .shader_type()
+        };
+
+        assert_eq!(
+            attribute(2, ComponentType::HalfFloat, false),
+            ShaderUniformType::Vec2
+        );
+        assert_eq!(
+            attribute(4, ComponentType::UnsignedByte, true),
+            ShaderUniformType::Vec4
+        );
+        assert_eq!(
+            attribute(4, ComponentType::UnsignedByte, false),
+            ShaderUniformType::UVec4
+        );
+        assert_eq!(
+            attribute(1, ComponentType::Short, false),
+            ShaderUniformType::Int
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn rejects_five_components() {
+        VertexAttribute::new(VertexChannel::Tangent, 5, ComponentType::Float, false);
+    }
+}
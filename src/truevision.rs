@@ -1,19 +1,55 @@
 use std::{fs::File, io::Read, path::PathBuf};
 
+use crate::image::{Image, PixelFormat};
+
 #[derive(Debug)]
 pub struct Targa {
-    pub width: usize,
-    pub height: usize,
-    pub bytes: Vec<u8>,
+    pub image: Image,
+    pub metadata: TargaMetadata,
+}
+
+#[derive(Debug, Default)]
+pub struct TargaMetadata {
+    pub image_id: Vec<u8>,
+    pub author: Option<String>,
+    pub timestamp: Option<TargaTimestamp>,
+    pub gamma: Option<f32>,
+    pub attribute_type: Option<TargaAttributeType>,
+    pub postage_stamp: Option<Image>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TargaTimestamp {
+    pub year: u16,
+    pub month: u16,
+    pub day: u16,
+    pub hour: u16,
+    pub minute: u16,
+    pub second: u16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargaAttributeType {
+    NoAlpha,
+    UndefinedIgnore,
+    UndefinedRetain,
+    Alpha,
+    PremultipliedAlpha,
+    Reserved(u8),
 }
 
 #[derive(Debug)]
 pub enum TargaError {
     IoError(std::io::Error),
     InvalidHeader,
+    TruncatedHeader,
+    TruncatedPixelData,
+    ZeroDimensions,
+    SizeOverflow,
     UnsupportedImageType(TargaImageType),
     UnsupportedBitDepth(u8),
     UnsupportedOrdering(HorizontalOrdering, VerticalOrdering),
+    InvalidExtensionArea,
 }
 
 impl From<std::io::Error> for TargaError {
@@ -149,38 +185,249 @@ impl TargaHeader {
     }
 }
 
+const FOOTER_SIZE: usize = 26;
+const FOOTER_SIGNATURE: &[u8] = b"TRUEVISION-XFILE.\0";
+const EXTENSION_AREA_SIZE: usize = 495;
+
+#[derive(Debug)]
+struct TargaFooter {
+    extension_area_offset: u32,
+}
+
+impl TargaFooter {
+    /// Returns `None` for original (TGA 1.0) files, which have no footer
+    fn from_bytes(data: &[u8]) -> Option<Self> {
+        if data.len() < FOOTER_SIZE {
+            return None;
+        }
+
+        let footer = &data[(data.len() - FOOTER_SIZE)..];
+
+        if &footer[8..] != FOOTER_SIGNATURE {
+            return None;
+        }
+
+        let extension_area_offset =
+            u32::from_le_bytes([footer[0], footer[1], footer[2], footer[3]]);
+
+        Some(Self {
+            extension_area_offset,
+        })
+    }
+}
+
+#[derive(Debug)]
+struct TargaExtensionArea {
+    author: Option<String>,
+    timestamp: Option<TargaTimestamp>,
+    gamma: Option<f32>,
+    postage_stamp_offset: u32,
+    attribute_type: TargaAttributeType,
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+impl TargaExtensionArea {
+    fn from_bytes(bytes: &[u8]) -> Result<Self, TargaError> {
+        if bytes.len() < EXTENSION_AREA_SIZE || read_u16(bytes, 0) as usize != EXTENSION_AREA_SIZE {
+            return Err(TargaError::InvalidExtensionArea);
+        }
+
+        // Null-terminated ASCII, padded with nulls or spaces
+        let author = {
+            let field = &bytes[2..43];
+            let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+            let author = String::from_utf8_lossy(&field[..end]).trim().to_string();
+
+            if author.is_empty() {
+                None
+            } else {
+                Some(author)
+            }
+        };
+
+        let timestamp = {
+            let timestamp = TargaTimestamp {
+                month: read_u16(bytes, 367),
+                day: read_u16(bytes, 369),
+                year: read_u16(bytes, 371),
+                hour: read_u16(bytes, 373),
+                minute: read_u16(bytes, 375),
+                second: read_u16(bytes, 377),
+            };
+
+            // All fields set to zero means the timestamp is not used
+            if bytes[367..379].iter().all(|&b| b == 0) {
+                None
+            } else {
+                Some(timestamp)
+            }
+        };
+
+        let gamma = {
+            let numerator = read_u16(bytes, 478);
+            let denominator = read_u16(bytes, 480);
+
+            if denominator == 0 {
+                None
+            } else {
+                Some(numerator as f32 / denominator as f32)
+            }
+        };
+
+        let postage_stamp_offset = read_u32(bytes, 486);
+
+        let attribute_type = match bytes[494] {
+            0 => TargaAttributeType::NoAlpha,
+            1 => TargaAttributeType::UndefinedIgnore,
+            2 => TargaAttributeType::UndefinedRetain,
+            3 => TargaAttributeType::Alpha,
+            4 => TargaAttributeType::PremultipliedAlpha,
+            other => TargaAttributeType::Reserved(other),
+        };
+
+        Ok(Self {
+            author,
+            timestamp,
+            gamma,
+            postage_stamp_offset,
+            attribute_type,
+        })
+    }
+}
+
+fn pixel_format(bits_per_pixel: u8) -> PixelFormat {
+    match bits_per_pixel {
+        32 => PixelFormat::Bgra8,
+        _ => PixelFormat::Bgr8,
+    }
+}
+
 impl Targa {
+    /// Reads the metadata of the extension area, if the file has one
+    fn read_metadata(data: &[u8], header: &TargaHeader) -> Result<TargaMetadata, TargaError> {
+        let mut metadata = TargaMetadata::default();
+
+        let footer = match TargaFooter::from_bytes(data) {
+            Some(footer) => footer,
+            None => return Ok(metadata),
+        };
+
+        if footer.extension_area_offset == 0 {
+            return Ok(metadata);
+        }
+
+        let extension_area = data
+            .get(footer.extension_area_offset as usize..)
+            .ok_or(TargaError::InvalidExtensionArea)?;
+        let extension_area = TargaExtensionArea::from_bytes(extension_area)?;
+
+        metadata.author = extension_area.author;
+        metadata.timestamp = extension_area.timestamp;
+        metadata.gamma = extension_area.gamma;
+        metadata.attribute_type = Some(extension_area.attribute_type);
+
+        if extension_area.postage_stamp_offset != 0 {
+            let offset = extension_area.postage_stamp_offset as usize;
+            let bits_per_pixel = header.image_specification.bits_per_pixel;
+
+            let size = data
+                .get(offset..offset.saturating_add(2))
+                .ok_or(TargaError::InvalidExtensionArea)?;
+            let (width, height) = (size[0] as usize, size[1] as usize);
+
+            let number_of_bytes = width * height * (bits_per_pixel / 8) as usize;
+            let pixel_end = (offset + 2)
+                .checked_add(number_of_bytes)
+                .ok_or(TargaError::SizeOverflow)?;
+            let pixel_data = data
+                .get((offset + 2)..pixel_end)
+                .ok_or(TargaError::InvalidExtensionArea)?;
+
+            metadata.postage_stamp = Some(Image::new(
+                width,
+                height,
+                pixel_format(bits_per_pixel),
+                pixel_data.to_vec(),
+            ));
+        }
+
+        Ok(metadata)
+    }
+
     pub fn from_file<P: Into<PathBuf>>(path: P) -> Result<Self, TargaError> {
         let mut file = File::open(path.into())?;
 
         let mut data = Vec::with_capacity(4096);
-        let mut bytes = Vec::with_capacity(4096);
 
         file.read_to_end(&mut data)?;
 
-        let header = TargaHeader::from_bytes(&data[..18])?;
+        Self::from_bytes(&data)
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, TargaError> {
+        let header = data.get(..18).ok_or(TargaError::TruncatedHeader)?;
+        let header = TargaHeader::from_bytes(header)?;
 
         match header.image_type {
             TargaImageType::UncompressedTrueColor => {}
             other => return Err(TargaError::UnsupportedImageType(other)),
         };
 
-        let number_of_pixels =
-            header.image_specification.width as usize * header.image_specification.height as usize;
+        let width = header.image_specification.width as usize;
+        let height = header.image_specification.height as usize;
+
+        if width == 0 || height == 0 {
+            return Err(TargaError::ZeroDimensions);
+        }
 
-        let number_of_bytes = match header.image_specification.bits_per_pixel {
-            24 => 3 * number_of_pixels,
-            32 => 4 * number_of_pixels,
+        let bytes_per_pixel = match header.image_specification.bits_per_pixel {
+            24 => 3,
+            32 => 4,
             other => return Err(TargaError::UnsupportedBitDepth(other)),
         };
 
-        if number_of_bytes > data.len() + 18 {
-            return Err(TargaError::InvalidHeader);
-        }
+        let number_of_bytes = width
+            .checked_mul(height)
+            .and_then(|pixels| pixels.checked_mul(bytes_per_pixel))
+            .ok_or(TargaError::SizeOverflow)?;
+
+        let image_id_end = 18 + header.id_length as usize;
+        let image_id = data
+            .get(18..image_id_end)
+            .ok_or(TargaError::TruncatedHeader)?
+            .to_vec();
 
-        let pixel_data = &data[18..(number_of_bytes + 18)];
+        let color_map_size = if header.color_map_included {
+            let entry_bits = header.color_map_specification.color_map_entry_size as usize;
+            header.color_map_specification.color_map_length as usize * entry_bits.div_ceil(8)
+        } else {
+            0
+        };
 
-        let window_size = header.image_specification.bits_per_pixel / 8;
+        let pixel_offset = image_id_end + color_map_size;
+
+        if pixel_offset > data.len() {
+            return Err(TargaError::TruncatedHeader);
+        }
+
+        let pixel_end = pixel_offset
+            .checked_add(number_of_bytes)
+            .ok_or(TargaError::SizeOverflow)?;
+        let pixel_data = data
+            .get(pixel_offset..pixel_end)
+            .ok_or(TargaError::TruncatedPixelData)?;
 
         if header.image_specification.horizontal_ordering != HorizontalOrdering::LeftToRight
             || header.image_specification.vertical_ordering != VerticalOrdering::TopToBottom
@@ -191,16 +438,186 @@ impl Targa {
             ));
         }
 
-        for pixel in pixel_data.chunks(window_size as usize) {
-            bytes.push(pixel[0]);
-            bytes.push(pixel[1]);
-            bytes.push(pixel[2]);
+        let image = Image::new(
+            width,
+            height,
+            pixel_format(header.image_specification.bits_per_pixel),
+            pixel_data.to_vec(),
+        );
+
+        // The extension area only holds optional metadata, so the image is
+        // still returned without it when it is broken
+        let metadata = Self::read_metadata(data, &header).unwrap_or_else(|e| {
+            eprintln!("Ignoring invalid TGA extension area: {e:?}");
+            TargaMetadata::default()
+        });
+        let metadata = TargaMetadata {
+            image_id,
+            ..metadata
+        };
+
+        Ok(Self { image, metadata })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A valid 2x2, 24-bit, top-left origin image with a 3-byte image ID
+    /// and a TGA 2.0 footer pointing to an extension area with a postage stamp
+    fn valid_file() -> Vec<u8> {
+        let mut data = vec![3, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 0, 2, 0, 24, 0x20];
+        data.extend_from_slice(b"scp");
+        data.extend_from_slice(&[10, 20, 30, 40, 50, 60, 70, 80, 90, 100, 110, 120]);
+
+        let extension_area_offset = data.len() as u32;
+        let mut extension_area = vec![0; EXTENSION_AREA_SIZE];
+        extension_area[..2].copy_from_slice(&(EXTENSION_AREA_SIZE as u16).to_le_bytes());
+        extension_area[2..6].copy_from_slice(b"jrdn");
+        extension_area[478..480].copy_from_slice(&22u16.to_le_bytes());
+        extension_area[480..482].copy_from_slice(&10u16.to_le_bytes());
+        let postage_stamp_offset = (data.len() + EXTENSION_AREA_SIZE) as u32;
+        extension_area[486..490].copy_from_slice(&postage_stamp_offset.to_le_bytes());
+        extension_area[494] = 2;
+        data.extend(extension_area);
+
+        data.extend_from_slice(&[1, 1, 1, 2, 3]);
+
+        data.extend_from_slice(&extension_area_offset.to_le_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.extend_from_slice(FOOTER_SIGNATURE);
+        data
+    }
+
+    fn with(mut data: Vec<u8>, offset: usize, bytes: &[u8]) -> Vec<u8> {
+        data[offset..(offset + bytes.len())].copy_from_slice(bytes);
+        data
+    }
+
+    #[test]
+    fn decodes_valid_file() {
+        let targa = Targa::from_bytes(&valid_file()).unwrap();
+
+        assert_eq!((targa.image.width, targa.image.height), (2, 2));
+        assert_eq!(targa.image.format, PixelFormat::Bgr8);
+        assert_eq!(targa.image.pixel(0, 0), [10, 20, 30]);
+        assert_eq!(targa.metadata.image_id, b"scp");
+        assert_eq!(targa.metadata.author.as_deref(), Some("jrdn"));
+        assert_eq!(targa.metadata.gamma, Some(2.2));
+        assert_eq!(
+            targa.metadata.attribute_type,
+            Some(TargaAttributeType::UndefinedRetain)
+        );
+        assert_eq!(targa.metadata.postage_stamp.unwrap().bytes, [1, 2, 3]);
+    }
+
+    #[test]
+    fn rejects_broken_corpus() {
+        let corpus: Vec<(&str, Vec<u8>)> = vec![
+            ("empty", vec![]),
+            ("one byte", vec![0]),
+            ("header minus one", valid_file()[..17].to_vec()),
+            ("header only", valid_file()[..18].to_vec()),
+            ("truncated image id", valid_file()[..20].to_vec()),
+            ("truncated pixels", valid_file()[..30].to_vec()),
+            ("zero width", with(valid_file(), 12, &[0, 0])),
+            ("zero height", with(valid_file(), 14, &[0, 0])),
+            ("huge dimensions", with(valid_file(), 12, &[0xFF; 4])),
+            (
+                "huge image id",
+                with(valid_file()[..40].to_vec(), 0, &[0xFF]),
+            ),
+            (
+                "huge color map",
+                with(valid_file(), 1, &[1, 0, 0, 0xFF, 0xFF, 32]),
+            ),
+            ("unknown image type", with(valid_file(), 2, &[42])),
+            ("compressed", with(valid_file(), 2, &[10])),
+            ("16-bit", with(valid_file(), 16, &[16])),
+            ("zero bit depth", with(valid_file(), 16, &[0])),
+            ("bottom-left origin", with(valid_file(), 17, &[0])),
+        ];
+
+        for (name, data) in corpus {
+            assert!(Targa::from_bytes(&data).is_err(), "{name} was accepted");
+        }
+    }
+
+    #[test]
+    fn ignores_broken_extension_area() {
+        let footer = valid_file().len() - FOOTER_SIZE;
+        // Cut in the middle of the extension area, keeping a footer that
+        // points to it
+        let mut truncated = valid_file()[..(33 + 100)].to_vec();
+        truncated.extend_from_slice(&valid_file()[footer..]);
+
+        let corpus: Vec<(&str, Vec<u8>)> = vec![
+            ("truncated", truncated),
+            (
+                "extension out of file",
+                with(valid_file(), footer, &[0xFF; 4]),
+            ),
+            (
+                "extension at end",
+                with(valid_file(), footer, &[0xFF, 0xFF, 0xFF, 0x7F]),
+            ),
+            ("extension wrong size", with(valid_file(), 33, &[0x10, 0])),
+            (
+                "stamp out of file",
+                with(valid_file(), 33 + 486, &[0xFF; 4]),
+            ),
+            (
+                "stamp too large",
+                with(valid_file(), 33 + EXTENSION_AREA_SIZE, &[0xFF; 2]),
+            ),
+        ];
+
+        for (name, data) in corpus {
+            let targa = Targa::from_bytes(&data).unwrap_or_else(|e| panic!("{name}: {e:?}"));
+
+            assert_eq!(targa.image.pixel(0, 0), [10, 20, 30], "{name}");
+            assert_eq!(targa.metadata.image_id, b"scp", "{name}");
+            assert!(targa.metadata.author.is_none(), "{name}");
+            assert!(targa.metadata.postage_stamp.is_none(), "{name}");
         }
+    }
 
-        Ok(Self {
-            bytes,
-            width: header.image_specification.width as usize,
-            height: header.image_specification.height as usize,
-        })
+    #[test]
+    fn reports_specific_errors() {
+        assert!(matches!(
+            Targa::from_bytes(&[0; 10]),
+            Err(TargaError::TruncatedHeader)
+        ));
+        assert!(matches!(
+            Targa::from_bytes(&valid_file()[..30]),
+            Err(TargaError::TruncatedPixelData)
+        ));
+        assert!(matches!(
+            Targa::from_bytes(&with(valid_file(), 12, &[0, 0])),
+            Err(TargaError::ZeroDimensions)
+        ));
+    }
+
+    #[test]
+    fn never_panics_on_truncation() {
+        let data = valid_file();
+
+        for length in 0..data.len() {
+            let _ = Targa::from_bytes(&data[..length]);
+        }
+    }
+
+    #[test]
+    fn never_panics_on_corruption() {
+        let data = valid_file();
+
+        for offset in 0..data.len() {
+            for value in [0x00, 0x01, 0x02, 0x0A, 0x20, 0x7F, 0x80, 0xFF] {
+                let mut corrupted = data.clone();
+                corrupted[offset] = value;
+                let _ = Targa::from_bytes(&corrupted);
+            }
+        }
     }
 }